@@ -0,0 +1,180 @@
+//! Designer mode editing the generated world
+//!
+//! Brushes change heights, biomes and resources of the tiles in a selection. Each application is
+//! recorded with the state of the changed tiles before and after, so it can be undone and redone.
+//! Biomes follow from heights, so painting a biome moves tiles into its height band.
+use crate::glob::types::*;
+use crate::selection::{tile_center, Selection, TilePos};
+use crate::world::island::tile::{Biome, Resource, LEVEL_HEIGHT, MAX_HEIGHT, MIN_HEIGHT};
+use crate::world::World;
+
+/// Edits kept for undo
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditTool {
+    Raise,
+    Lower,
+    /// level towards the mean height of the selection
+    Flatten,
+    /// level towards the mean height of each tile's neighbourhood
+    Smooth,
+    PaintBiome(Biome),
+    /// set or, with `None`, clear the resource
+    PaintResource(Option<Resource>),
+}
+
+impl EditTool {
+    /// True if the tool can change the tile at `pos`
+    pub fn is_valid(&self, world: &World, pos: TilePos) -> bool {
+        match world.tile(tile_center(pos)) {
+            None => false,
+            Some(tile) => match self {
+                // resources only grow on land
+                EditTool::PaintResource(Some(_)) => tile.biome() != Biome::Water,
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Part of a tile the editor changes
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct TileState {
+    height: f32,
+    resource: Option<Resource>,
+}
+
+/// One application of a tool
+struct Edit {
+    /// changed tiles with their state before and after
+    changes: Vec<(TilePos, TileState, TileState)>,
+}
+
+#[derive(Default)]
+pub struct Editor {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl Editor {
+    /// Apply `tool` to the tiles of `selection` it is valid on
+    ///
+    /// `strength` is the height change in levels for raising and lowering and the blend factor
+    /// towards the target height, at most 1, for flattening and smoothing.
+    pub fn apply(&mut self, world: &mut World, tool: EditTool, strength: f32, selection: &Selection) {
+        let tiles: Vec<(TilePos, TileState)> = selection
+            .tiles()
+            .filter(|pos| tool.is_valid(world, *pos))
+            .filter_map(|pos| state_at(world, pos).map(|state| (pos, state)))
+            .collect();
+        if tiles.is_empty() {
+            return;
+        }
+        let blend = strength.clamp(0.0, 1.0);
+        let mean_height = tiles.iter().map(|(_, state)| state.height).sum::<f32>() / tiles.len() as f32;
+        // all new states are computed from the old ones, so smoothing does not depend on the order
+        let changes: Vec<(TilePos, TileState, TileState)> = tiles
+            .iter()
+            .map(|&(pos, before)| {
+                let mut after = before;
+                match tool {
+                    EditTool::Raise => after.height += strength * LEVEL_HEIGHT,
+                    EditTool::Lower => after.height -= strength * LEVEL_HEIGHT,
+                    EditTool::Flatten => after.height += (mean_height - before.height) * blend,
+                    EditTool::Smooth => after.height += (neighbourhood_height(world, pos) - before.height) * blend,
+                    EditTool::PaintBiome(biome) => after.height = biome.clamp_height(before.height),
+                    EditTool::PaintResource(resource) => after.resource = resource,
+                }
+                after.height = after.height.clamp(MIN_HEIGHT, MAX_HEIGHT);
+                (pos, before, after)
+            })
+            .filter(|(_, before, after)| before != after)
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        let edit = Edit { changes };
+        edit.apply(world, false);
+        self.undo.push(edit);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Drop the recorded changes of tiles in `area` from the undo and redo history
+    pub fn forget(&mut self, area: WorldRect) {
+        for history in [&mut self.undo, &mut self.redo] {
+            for edit in history.iter_mut() {
+                edit.changes.retain(|(pos, _, _)| !area.contains(tile_center(*pos)));
+            }
+            history.retain(|edit| !edit.changes.is_empty());
+        }
+    }
+
+    /// Revert the last edit. Returns false if there is nothing to undo
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        match self.undo.pop() {
+            Some(edit) => {
+                edit.apply(world, true);
+                self.redo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Repeat the last undone edit. Returns false if there is nothing to redo
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                edit.apply(world, false);
+                self.undo.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Edit {
+    /// Set the changed tiles to their state after the edit, or before it if `revert`
+    fn apply(&self, world: &mut World, revert: bool) {
+        for (pos, before, after) in &self.changes {
+            if let Some(tile) = world.tile_mut(tile_center(*pos)) {
+                let state = if revert { before } else { after };
+                tile.set_height(state.height);
+                tile.set_resource(state.resource);
+            }
+        }
+        // neighbours draw cliffs down to changed tiles, so their chunks are touched as well
+        let area = WorldRect::from_points(self.changes.iter().flat_map(|(pos, _, _)| {
+            let min = WorldCoordinate::new(pos.x as f32, pos.y as f32);
+            [min, min + WorldVector::new(1.0, 1.0)]
+        }));
+        world.touch_chunks(area.inflate(1.0, 1.0));
+    }
+}
+
+fn state_at(world: &World, pos: TilePos) -> Option<TileState> {
+    world.tile(tile_center(pos)).map(|tile| TileState {
+        height: tile.height(),
+        resource: tile.resource(),
+    })
+}
+
+/// Mean height of the tile at `pos` and its eight neighbours that exist
+fn neighbourhood_height(world: &World, pos: TilePos) -> f32 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            if let Some(state) = state_at(world, pos + euclid::default::Vector2D::new(dx, dy)) {
+                sum += state.height;
+                count += 1;
+            }
+        }
+    }
+    sum / count.max(1) as f32
+}
@@ -1,52 +1,61 @@
 //! The `game` reacts to output of `Renderer` and alters the world accordingly
-use crate::glob::types::*;
+use crate::editor::Editor;
+use crate::selection::{tile_center, Selection, TilePos};
 use crate::user_cmds::*;
 use crate::world::*;
-use allegro::KeyCode;
-pub fn update(world: &mut World, renderer_feedback: &RendererFeedback) {
-    // determine chunks that lie inside the rendered world area
-    let start_chunk = ChunkIndex::new(
-        f32::floor(renderer_feedback.loaded_world_area.min_x() / CHUNK_SIZE) as isize,
-        f32::floor(renderer_feedback.loaded_world_area.min_y() / CHUNK_SIZE) as isize,
-    );
-    let end_chunk = ChunkIndex::new(
-        (renderer_feedback.loaded_world_area.max_x() / CHUNK_SIZE) as isize,
-        (renderer_feedback.loaded_world_area.max_y() / CHUNK_SIZE) as isize,
-    );
+pub fn update(world: &mut World, editor: &mut Editor, renderer_feedback: &RendererFeedback) {
+    // determine chunks that lie inside the rendered world area. The camera is kept inside the
+    // generated chunks, so every partly visible chunk is needed for the world to grow
+    let start_chunk = chunk_index(renderer_feedback.loaded_world_area.min());
+    let end_chunk = chunk_index(renderer_feedback.loaded_world_area.max());
     let mut needed_chunks: Vec<ChunkIndex> = Vec::new();
-    if start_chunk == end_chunk {
-        if world.chunks.contains_key(&start_chunk) {
-        } else {
-            needed_chunks.push(start_chunk);
-        }
-    } else {
-        for x in start_chunk.x..end_chunk.x {
-            for y in start_chunk.y..end_chunk.y {
-                if world.chunks.contains_key(&ChunkIndex::new(x, y)) {
-                    continue;
-                }
-                needed_chunks.push(ChunkIndex::new(x, y));
+    for x in start_chunk.x..=end_chunk.x {
+        for y in start_chunk.y..=end_chunk.y {
+            if world.chunks.contains_key(&ChunkIndex::new(x, y)) {
+                continue;
             }
+            needed_chunks.push(ChunkIndex::new(x, y));
         }
     }
     // generate chunks that are missing
     for ind in needed_chunks {
         world.gen_chunk(ind);
     }
-    const SPEED: f32 = 0.5;
-    if renderer_feedback.key_states[KeyCode::W as usize] == KeyState::Pressed {
-        world.screen_pos -= WorldVector::new(SPEED, SPEED);
-    }
-    if renderer_feedback.key_states[KeyCode::A as usize] == KeyState::Pressed {
-        world.screen_pos += WorldVector::new(-SPEED, SPEED);
+    if let Some(selection) = &renderer_feedback.selection {
+        apply_tool(world, editor, renderer_feedback, selection);
     }
-    if renderer_feedback.key_states[KeyCode::S as usize] == KeyState::Pressed {
-        world.screen_pos += WorldVector::new(SPEED, SPEED);
+    for cmd in &renderer_feedback.history {
+        let done = match cmd {
+            HistoryCmd::Undo => editor.undo(world),
+            HistoryCmd::Redo => editor.redo(world),
+        };
+        if !done {
+            log::info!("Nothing to {:?}", cmd);
+        }
     }
-    if renderer_feedback.key_states[KeyCode::D as usize] == KeyState::Pressed {
-        world.screen_pos += WorldVector::new(SPEED, -SPEED);
+    if let Some((index, params)) = renderer_feedback.regenerate_island {
+        match world.regenerate_island(index, params) {
+            // edits of the old terrain must not be replayed on the new one
+            Ok(area) => editor.forget(area),
+            Err(e) => log::warn!("{}", e),
+        }
     }
-    if renderer_feedback.mouse.right {
-        world.screen_pos += renderer_feedback.mouse.pos_diff;
+}
+
+/// Let the selected tool act on the tiles of `selection` it is valid on
+fn apply_tool(world: &mut World, editor: &mut Editor, renderer_feedback: &RendererFeedback, selection: &Selection) {
+    let tool = renderer_feedback.tool;
+    match tool {
+        Tool::Inspect => {}
+        Tool::Edit(edit_tool) => editor.apply(world, edit_tool, renderer_feedback.brush_strength, selection),
+        Tool::Build(building) => {
+            let valid: Vec<TilePos> = selection.tiles().filter(|pos| tool.is_valid(world, *pos)).collect();
+            for pos in valid {
+                if let Some(tile) = world.tile_mut(tile_center(pos)) {
+                    tile.set_building(Some(building));
+                }
+            }
+            world.touch_chunks(selection.bounds());
+        }
     }
 }
@@ -10,3 +10,6 @@ pub const TILE_TEXTURE_SIZE: types::ScreenCoordinate = types::ScreenCoordinate::
 pub static TILE_SIZE: types::ScreenCoordinate = types::ScreenCoordinate::new(
     TILE_LEN,
     (TILE_LEN) * PERSPECTIVE_DISTORTION_Y);
+
+/// vertical screen offset per elevation level at zoom 1
+pub const LEVEL_OFFSET: f32 = TILE_LEN * PERSPECTIVE_DISTORTION_Y / 2.0;
@@ -30,39 +30,101 @@ pub type ScreenVector = euclid::Vector2D<f32, ScreenSpace>;
 pub type WorldRect = euclid::Rect<f32, WorldSpace>;
 pub type ScreenRect = euclid::Rect<f32, ScreenSpace>;
 
+/// Corners of `rect` in clockwise order, starting at its minimum
+pub fn rect_corners<U>(rect: &euclid::Rect<f32, U>) -> [euclid::Point2D<f32, U>; 4] {
+    [
+        rect.min(),
+        euclid::Point2D::new(rect.max_x(), rect.min_y()),
+        rect.max(),
+        euclid::Point2D::new(rect.min_x(), rect.max_y()),
+    ]
+}
+
 /// Returns the smallest rectangular area of the world that contains the entire screen.
 pub fn visible_world_rect(screen: ScreenRect, s2w: euclid::Transform2D<f32, ScreenSpace, WorldSpace>) -> WorldRect {
-    let upper_left_world = s2w.transform_point(
-        ScreenCoordinate::new(
-            - screen.max_x() / 2.0,
-            - screen.max_y() / 2.0,
-        ));
-    let lower_right_world = s2w.transform_point(
-        ScreenCoordinate::new(
-            screen.max_x() / 2.0,
-            screen.max_y() / 2.0,
-        ));
-    let points = vec![
-        upper_left_world,
-        lower_right_world,
-    ];
-    WorldRect::from_points(points.into_iter())
+    // the screen is a rotated rectangle in world space, so all corners are needed
+    WorldRect::from_points(rect_corners(&screen).map(|corner| s2w.transform_point(corner)))
+}
+
+/// Orientation of the camera in quarter turns of the world on screen, clockwise
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rotation(u8);
+
+impl Rotation {
+    pub fn clockwise(self) -> Self {
+        Rotation((self.0 + 1) % 4)
+    }
+    pub fn counter_clockwise(self) -> Self {
+        Rotation((self.0 + 3) % 4)
+    }
+    /// rotation that undoes this one
+    pub fn inverse(self) -> Self {
+        Rotation((4 - self.0) % 4)
+    }
+    /// rotate world vector `v` by this rotation
+    pub fn apply(self, v: WorldVector) -> WorldVector {
+        self.transform().transform_vector(v)
+    }
+    /// rotation as transformation around the world origin
+    pub fn transform(self) -> euclid::Transform2D<f32, WorldSpace, WorldSpace> {
+        // columns are the images of the x and y axes
+        let (x_axis, y_axis) = match self.0 {
+            0 => ((1.0, 0.0), (0.0, 1.0)),
+            1 => ((0.0, 1.0), (-1.0, 0.0)),
+            2 => ((-1.0, 0.0), (0.0, -1.0)),
+            _ => ((0.0, -1.0), (1.0, 0.0)),
+        };
+        euclid::Transform2D::new(x_axis.0, x_axis.1, y_axis.0, y_axis.1, 0.0, 0.0)
+    }
+    /// distance of world vector `v` towards the viewer. Larger is further in front
+    pub fn depth(self, v: WorldVector) -> f32 {
+        let rotated = self.apply(v);
+        rotated.x + rotated.y
+    }
+    /// offset of the tile corner that is topmost on screen from the tile position
+    pub fn tile_top(self) -> WorldVector {
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .map(|(x, y)| WorldVector::new(x, y))
+            .into_iter()
+            .min_by(|a, b| self.depth(*a).total_cmp(&self.depth(*b)))
+            .unwrap()
+    }
 }
 
 /// generate transformation matrix from screen to world coordinates
-pub fn gen_s2w_matrix(zoom: f32, camera: WorldCoordinate) -> euclid::Transform2D<f32, ScreenSpace, WorldSpace> {
-    euclid::Transform2D::<f32, ScreenSpace, WorldSpace>::new(
-        1.0 / (zoom * TILE_SIZE.x), -1.0/(zoom * TILE_SIZE.x),
-        1.0 / (zoom * TILE_SIZE.y), 1.0/(zoom * TILE_SIZE.y),
-        camera.x, camera.y,
-    )
+///
+/// `camera` is the world position shown at `screen_center`. The world is turned by `rotation` around it
+pub fn gen_s2w_matrix(
+    zoom: f32,
+    camera: WorldCoordinate,
+    screen_center: ScreenCoordinate,
+    rotation: Rotation,
+) -> euclid::Transform2D<f32, ScreenSpace, WorldSpace> {
+    euclid::Transform2D::<f32, ScreenSpace, ScreenSpace>::translation(-screen_center.x, -screen_center.y)
+        .then(&euclid::Transform2D::<f32, ScreenSpace, WorldSpace>::new(
+            1.0 / (zoom * TILE_SIZE.x), -1.0/(zoom * TILE_SIZE.x),
+            1.0 / (zoom * TILE_SIZE.y), 1.0/(zoom * TILE_SIZE.y),
+            0.0, 0.0,
+        ))
+        .then(&rotation.inverse().transform())
+        .then_translate(camera.to_vector())
 }
 
 /// generate transformation matrix from world to screen coordinates
-pub fn gen_w2s_matrix(zoom: f32, camera: WorldCoordinate) -> euclid::Transform2D<f32, WorldSpace, ScreenSpace> {
-    euclid::Transform2D::<f32, WorldSpace, ScreenSpace>::new(
-        zoom * TILE_SIZE.x / 2.0, zoom * TILE_SIZE.y / 2.0,
-        - zoom * TILE_SIZE.x / 2.0, zoom * TILE_SIZE.y / 2.0,
-        - camera.x - camera.y, - camera.x - camera.y,
-    )
+///
+/// `camera` is the world position shown at `screen_center`. The world is turned by `rotation` around it
+pub fn gen_w2s_matrix(
+    zoom: f32,
+    camera: WorldCoordinate,
+    screen_center: ScreenCoordinate,
+    rotation: Rotation,
+) -> euclid::Transform2D<f32, WorldSpace, ScreenSpace> {
+    euclid::Transform2D::<f32, WorldSpace, WorldSpace>::translation(-camera.x, -camera.y)
+        .then(&rotation.transform())
+        .then(&euclid::Transform2D::<f32, WorldSpace, ScreenSpace>::new(
+            zoom * TILE_SIZE.x / 2.0, zoom * TILE_SIZE.y / 2.0,
+            - zoom * TILE_SIZE.x / 2.0, zoom * TILE_SIZE.y / 2.0,
+            0.0, 0.0,
+        ))
+        .then_translate(screen_center.to_vector())
 }
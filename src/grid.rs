@@ -0,0 +1,430 @@
+//! Dense 2D grids
+//!
+//! A `Grid` stores its cells in one allocation, row by row, so walking a row touches consecutive
+//! memory. Cells are addressed by column `x` and row `y`.
+use std::ops::{Index, IndexMut};
+
+/// Fewest rows a thread of `Grid::par_from_fn` works on, so small grids are not split up
+const MIN_ROWS_PER_THREAD: usize = 16;
+
+/// Column and row of a cell
+pub type GridIndex = euclid::default::Point2D<usize>;
+/// Cells of a grid, `origin` is the upper left cell
+pub type GridRect = euclid::default::Rect<usize>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+/// Rectangular part of a grid
+#[derive(Clone, Copy)]
+pub struct GridView<'a, T> {
+    grid: &'a Grid<T>,
+    rect: GridRect,
+}
+
+impl<T> Grid<T> {
+    /// Grid of `width` x `height` cells with the values of `f(x, y)`
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(f(x, y));
+            }
+        }
+        Grid { width, height, cells }
+    }
+
+    /// Grid of `width` x `height` cells from `cells` given row by row. `None` if the length does not fit
+    pub fn from_vec(width: usize, height: usize, cells: Vec<T>) -> Option<Self> {
+        (cells.len() == width * height).then_some(Grid { width, height, cells })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// True if the grid has no cells
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        self.contains(x, y).then(|| &self.cells[y * self.width + x])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        if self.contains(x, y) {
+            Some(&mut self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Cell at signed coordinates, `None` outside of the grid. Convenient for neighbour offsets
+    pub fn at(&self, x: isize, y: isize) -> Option<&T> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.get(x as usize, y as usize)
+    }
+
+    /// Cell without bounds check
+    ///
+    /// # Safety
+    /// `x` must be less than `width` and `y` less than `height`. Cells outside are undefined behaviour,
+    /// even if the index still falls inside the storage
+    pub unsafe fn get_unchecked(&self, x: usize, y: usize) -> &T {
+        self.cells.get_unchecked(y * self.width + x)
+    }
+
+    /// Mutable cell without bounds check
+    ///
+    /// # Safety
+    /// Same as `get_unchecked`
+    pub unsafe fn get_unchecked_mut(&mut self, x: usize, y: usize) -> &mut T {
+        self.cells.get_unchecked_mut(y * self.width + x)
+    }
+
+    /// All cells row by row
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.cells.iter_mut()
+    }
+
+    /// All cells row by row with their index
+    pub fn indexed_iter(&self) -> impl Iterator<Item = (GridIndex, &T)> {
+        let width = self.width.max(1);
+        self.cells.iter().enumerate().map(move |(i, cell)| (GridIndex::new(i % width, i / width), cell))
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> + ExactSizeIterator {
+        self.cells.chunks_exact(self.width.max(1))
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> {
+        self.cells.chunks_exact_mut(self.width.max(1))
+    }
+
+    /// Cells of column `x` from top to bottom
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        assert!(x < self.width, "Column {} outside of {} x {} grid", x, self.width, self.height);
+        self.cells.iter().skip(x).step_by(self.width).take(self.height)
+    }
+
+    pub fn columns(&self) -> impl DoubleEndedIterator<Item = impl Iterator<Item = &T>> + ExactSizeIterator {
+        (0..self.width).map(move |x| self.column(x))
+    }
+
+    /// Part of the grid inside `rect`, clipped to the grid
+    pub fn view(&self, rect: GridRect) -> GridView<'_, T> {
+        let bounds = GridRect::new(GridIndex::zero(), euclid::default::Size2D::new(self.width, self.height));
+        GridView {
+            grid: self,
+            rect: rect.intersection(&bounds).unwrap_or_default(),
+        }
+    }
+
+    /// Cells at most `radius` columns and rows away from `x`, `y`, clipped to the grid
+    pub fn window(&self, x: usize, y: usize, radius: usize) -> GridView<'_, T> {
+        let min = GridIndex::new(x.saturating_sub(radius), y.saturating_sub(radius));
+        let max = GridIndex::new(x + radius + 1, y + radius + 1);
+        self.view(GridRect::new(min, (max - min).to_size()))
+    }
+
+    /// Grid of the same size with `f` applied to every cell
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T: Send> Grid<T> {
+    /// Like `from_fn`, with blocks of rows computed on all available threads
+    ///
+    /// Every cell is computed on its own by `f`, so the result does not depend on the number of threads.
+    pub fn par_from_fn(width: usize, height: usize, f: impl Fn(usize, usize) -> T + Sync) -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        Grid::from_fn_on_threads(width, height, threads, f)
+    }
+
+    /// `par_from_fn` on at most `threads` threads
+    fn from_fn_on_threads(width: usize, height: usize, threads: usize, f: impl Fn(usize, usize) -> T + Sync) -> Self {
+        let rows_per_thread = height.div_ceil(threads).max(MIN_ROWS_PER_THREAD);
+        if rows_per_thread >= height {
+            return Grid::from_fn(width, height, f);
+        }
+        let f = &f;
+        let cells = std::thread::scope(|scope| {
+            let blocks: Vec<_> = (0..height)
+                .step_by(rows_per_thread)
+                .map(|first_row| {
+                    scope.spawn(move || {
+                        let rows = first_row..(first_row + rows_per_thread).min(height);
+                        let mut cells = Vec::with_capacity(rows.len() * width);
+                        for y in rows {
+                            for x in 0..width {
+                                cells.push(f(x, y));
+                            }
+                        }
+                        cells
+                    })
+                })
+                .collect();
+            // in order of the rows
+            let mut cells = Vec::with_capacity(width * height);
+            for block in blocks {
+                cells.extend(block.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+            }
+            cells
+        });
+        Grid { width, height, cells }
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    /// Grid of `width` x `height` cells set to `value`
+    pub fn new(width: usize, height: usize, value: T) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![value; width * height],
+        }
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        assert!(self.contains(x, y), "Cell {} {} outside of {} x {} grid", x, y, self.width, self.height);
+        // checked above
+        unsafe { self.get_unchecked(x, y) }
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        assert!(self.contains(x, y), "Cell {} {} outside of {} x {} grid", x, y, self.width, self.height);
+        // checked above
+        unsafe { self.get_unchecked_mut(x, y) }
+    }
+}
+
+impl<T> Index<GridIndex> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, index: GridIndex) -> &T {
+        &self[(index.x, index.y)]
+    }
+}
+
+impl<T> IndexMut<GridIndex> for Grid<T> {
+    fn index_mut(&mut self, index: GridIndex) -> &mut T {
+        &mut self[(index.x, index.y)]
+    }
+}
+
+impl<'a, T> GridView<'a, T> {
+    pub fn width(&self) -> usize {
+        self.rect.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.rect.height()
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> {
+        let (grid, rect) = (self.grid, self.rect);
+        rect.y_range().map(move |y| &grid.row(y)[rect.x_range()])
+    }
+
+    /// All cells row by row
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> {
+        self.rows().flatten()
+    }
+
+    /// All cells row by row with their index in the grid
+    pub fn indexed_iter(&self) -> impl Iterator<Item = (GridIndex, &'a T)> {
+        let rect = self.rect;
+        self.rows()
+            .zip(rect.y_range())
+            .flat_map(move |(row, y)| row.iter().zip(rect.x_range()).map(move |(cell, x)| (GridIndex::new(x, y), cell)))
+    }
+}
+
+impl<'a, T: Clone> GridView<'a, T> {
+    /// Copy of the viewed cells
+    pub fn to_grid(&self) -> Grid<T> {
+        Grid {
+            width: self.width(),
+            height: self.height(),
+            cells: self.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 4 x 3 grid with the cells numbered row by row
+    fn numbered() -> Grid<usize> {
+        Grid::from_fn(4, 3, |x, y| y * 4 + x)
+    }
+
+    #[test]
+    fn from_vec_checks_length() {
+        assert_eq!(Grid::from_vec(2, 3, vec![0; 6]).map(|grid| grid.height()), Some(3));
+        assert!(Grid::from_vec(2, 3, vec![0; 5]).is_none());
+        assert!(Grid::from_vec(0, 3, Vec::<u8>::new()).is_some_and(|grid| grid.is_empty()));
+    }
+
+    #[test]
+    fn cells_are_row_major() {
+        let grid = numbered();
+        assert_eq!(grid[(1, 2)], 9);
+        assert_eq!(grid[GridIndex::new(3, 0)], 3);
+        assert_eq!(grid.row(1), &[4, 5, 6, 7]);
+        assert!(grid.iter().copied().eq(0..12));
+    }
+
+    #[test]
+    fn get_and_at_are_bounded() {
+        let grid = numbered();
+        assert_eq!(grid.get(3, 2), Some(&11));
+        assert_eq!(grid.get(4, 0), None);
+        assert_eq!(grid.get(0, 3), None);
+        assert_eq!(grid.at(-1, 0), None);
+        assert_eq!(grid.at(0, -1), None);
+        assert_eq!(grid.at(2, 1), Some(&6));
+        assert_eq!(grid.at(4, 1), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_outside_panics() {
+        // x past the width would still be inside the cells
+        let _ = numbered()[(4, 0)];
+    }
+
+    #[test]
+    fn unchecked_access_matches_index() {
+        let mut grid = numbered();
+        for (index, cell) in numbered().indexed_iter() {
+            assert_eq!(unsafe { grid.get_unchecked(index.x, index.y) }, cell);
+        }
+        unsafe { *grid.get_unchecked_mut(3, 1) = 100 };
+        assert_eq!(grid[(3, 1)], 100);
+        assert_eq!(grid.iter().filter(|cell| **cell == 100).count(), 1);
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let grid = numbered();
+        assert_eq!(grid.rows().len(), 3);
+        assert_eq!(grid.rows().next_back(), Some(&[8, 9, 10, 11][..]));
+        assert!(grid.column(1).copied().eq([1, 5, 9]));
+        assert!(grid.column(3).copied().eq([3, 7, 11]));
+        let columns: Vec<Vec<usize>> = grid.columns().map(|column| column.copied().collect()).collect();
+        assert_eq!(columns.len(), 4);
+        assert_eq!(columns[2], [2, 6, 10]);
+        assert!(grid.columns().next_back().unwrap().copied().eq([3, 7, 11]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn column_outside_panics() {
+        let _ = numbered().column(4);
+    }
+
+    #[test]
+    fn window_is_clipped() {
+        let grid = numbered();
+        assert!(grid.window(1, 1, 1).iter().copied().eq([0, 1, 2, 4, 5, 6, 8, 9, 10]));
+        // corners lose the rows and columns outside of the grid
+        assert!(grid.window(0, 0, 1).iter().copied().eq([0, 1, 4, 5]));
+        assert!(grid.window(3, 2, 1).iter().copied().eq([6, 7, 10, 11]));
+        assert!(grid.window(2, 1, 0).iter().copied().eq([6]));
+        let window = grid.window(3, 0, 2);
+        assert_eq!((window.width(), window.height()), (3, 3));
+        assert_eq!(window.indexed_iter().next().map(|(index, _)| (index.x, index.y)), Some((1, 0)));
+    }
+
+    #[test]
+    fn indexed_iter_matches_index() {
+        let grid = numbered();
+        assert_eq!(grid.indexed_iter().count(), 12);
+        for (index, cell) in grid.indexed_iter() {
+            assert_eq!(grid[index], *cell);
+        }
+    }
+
+    #[test]
+    fn view_is_clipped() {
+        let grid = numbered();
+        let view = grid.view(GridRect::new(GridIndex::new(2, 1), euclid::default::Size2D::new(5, 5)));
+        assert_eq!((view.width(), view.height()), (2, 2));
+        assert!(view.iter().copied().eq([6, 7, 10, 11]));
+        let indices: Vec<_> = view.indexed_iter().map(|(index, _)| (index.x, index.y)).collect();
+        assert_eq!(indices, [(2, 1), (3, 1), (2, 2), (3, 2)]);
+        assert_eq!(view.to_grid(), Grid::from_vec(2, 2, vec![6, 7, 10, 11]).unwrap());
+
+        let outside = grid.view(GridRect::new(GridIndex::new(4, 0), euclid::default::Size2D::new(2, 2)));
+        assert_eq!(outside.iter().count(), 0);
+        assert!(outside.to_grid().is_empty());
+    }
+
+    #[test]
+    fn zero_width() {
+        let grid = Grid::from_fn(0, 3, |_, _| 0);
+        assert!(grid.is_empty());
+        assert_eq!(grid.height(), 3);
+        assert_eq!(grid.get(0, 0), None);
+        assert_eq!(grid.indexed_iter().count(), 0);
+        assert!(grid.row(2).is_empty());
+        assert_eq!(grid.rows().count(), 0);
+        assert_eq!(grid.columns().count(), 0);
+        let view = grid.view(GridRect::new(GridIndex::zero(), euclid::default::Size2D::new(2, 2)));
+        assert_eq!(view.iter().count(), 0);
+    }
+
+    #[test]
+    fn threads_give_the_same_grid() {
+        let f = |x: usize, y: usize| (x as f32 * 0.37).sin() + y as f32 / 3.0;
+        let expected = Grid::from_fn(21, 100, f);
+        // the last block is shorter than the others
+        for threads in [1, 2, 3, 7] {
+            assert_eq!(Grid::from_fn_on_threads(21, 100, threads, f), expected, "{} threads", threads);
+        }
+        assert_eq!(Grid::par_from_fn(21, 100, f), expected);
+    }
+
+    #[test]
+    fn map_keeps_the_size() {
+        let grid = numbered().map(|cell| cell * 2);
+        assert_eq!((grid.width(), grid.height()), (4, 3));
+        assert_eq!(grid[(1, 1)], 10);
+    }
+}
@@ -12,12 +12,17 @@
 //!
 //! The `Renderer` paints a (readonly) world and detects user input.
 //! The `game` reacts to (readonly) user input and alters the `World` accordingly
+mod editor;
 mod game;
 mod glob;
+mod grid;
 mod renderer;
+mod selection;
 mod user_cmds;
 mod world;
 
+use glob::types::{WorldCoordinate, WorldRect};
+
 /// initialize log4rs framework
 fn configure_logging() {
     let config_str = include_str!("logging.yaml");
@@ -25,16 +30,76 @@ fn configure_logging() {
     log4rs::init_raw_config(config).expect("Cannot initialize log4rs config");
 }
 
+/// edge length in chunks of the area generated for `--export` without an area
+const EXPORT_CHUNKS: isize = 2;
+
+/// longest side in tiles of an area passed to `--export`
+const MAX_EXPORT_EXTENT: f32 = 1024.0;
+const EXPORT_USAGE: &str = "Usage: island_generator --export <dir> [<min_x> <min_y> <max_x> <max_y>]";
+
+/// World rectangle from the `--export` arguments `min_x min_y max_x max_y`
+fn parse_export_area(args: &[String]) -> Result<WorldRect, String> {
+    let mut values = [0.0; 4];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = match arg.parse::<f32>() {
+            Ok(value) if value.is_finite() => value,
+            _ => return Err(format!("Invalid export area coordinate '{}'", arg)),
+        };
+    }
+    let area = WorldRect::from_points([WorldCoordinate::new(values[0], values[1]), WorldCoordinate::new(values[2], values[3])]);
+    if area.width() > MAX_EXPORT_EXTENT || area.height() > MAX_EXPORT_EXTENT {
+        return Err(format!("Export area is larger than {} tiles per side", MAX_EXPORT_EXTENT));
+    }
+    Ok(area)
+}
+
 fn main() {
     configure_logging();
-    let mut world = world::World::default();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "--export") {
+        // generate the chosen area, or one around the origin, and write images of it without opening a window
+        let area = match args.len() {
+            3 => Ok(None),
+            7 => parse_export_area(&args[3..7]).map(Some),
+            _ => Err("Wrong number of arguments".to_string()),
+        };
+        let area = area.unwrap_or_else(|e| {
+            eprintln!("{}\n{}", e, EXPORT_USAGE);
+            std::process::exit(2);
+        });
+        let (first, last) = match area {
+            Some(area) => (world::chunk_index(area.min()), world::chunk_index(area.max())),
+            None => (world::ChunkIndex::new(0, 0), world::ChunkIndex::new(EXPORT_CHUNKS - 1, EXPORT_CHUNKS - 1)),
+        };
+        let mut world = world::World::default();
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                world.gen_chunk(world::ChunkIndex::new(x, y));
+            }
+        }
+        renderer::export::export_headless(&world, &args[2], area).expect("Export failed");
+        return;
+    }
+    // start from a saved world instead of an empty one
+    let mut world = if args.len() == 3 && args[1] == "--load" {
+        world::World::load(&args[2]).expect("Loading failed")
+    } else if args.len() == 3 && args[1] == "--recipe" {
+        // generate islands with a custom recipe
+        world::World {
+            recipe: Some(world::island::heightmap::Pipeline::load(&args[2]).expect("Loading recipe failed")),
+            ..world::World::default()
+        }
+    } else {
+        world::World::default()
+    };
+    let mut editor = editor::Editor::default();
     let mut renderer = renderer::Renderer::new(
         renderer::settings::Settings::default()
     ).expect("Failed to initialize renderer");
     loop {
         let renderer_fb = renderer.next_frame(&world);
         if renderer_fb.update_necessary {
-            game::update(&mut world, &renderer_fb);
+            game::update(&mut world, &mut editor, &renderer_fb);
         }
         if renderer_fb.exit {
             break;
@@ -1,13 +1,23 @@
 //! The renderer renders a `World` (readonly) and detects user input which it hands to the `game``
+mod autotile;
+mod camera;
+mod chunk_cache;
 mod egui_allegro_backend;
 mod engine;
+pub mod export;
 mod gui;
+mod lod;
 mod map;
+mod ocean;
+mod overlay;
+mod picking;
+mod stage_viewer;
+mod terrain;
 pub mod settings;
-use crate::glob;
 use map::MapRenderer;
 use crate::glob::types::*;
-use crate::user_cmds::{KeyState, MouseState, RendererFeedback, NUM_KEYS};
+use crate::selection::{self, Drag, Selection};
+use crate::user_cmds::{HistoryCmd, KeyState, MouseState, RendererFeedback, NUM_KEYS};
 use crate::world::World;
 use engine::Engine;
 use settings::Settings;
@@ -17,15 +27,27 @@ pub struct Renderer {
     engine: Engine,
     egui_engine: egui_allegro_backend::Backend,
     mouse: ScreenCoordinate,
-    apparent_tile_size: ScreenCoordinate,
+    /// false while the mouse is outside the window, which stops edge scrolling
+    mouse_on_display: bool,
+    camera: camera::Camera,
     rendered_screen_area: ScreenRect,
     screen_on_world: WorldRect,
     rendered_world_area: WorldRect,
     gui_info: gui::GuiInfo,
     last_draw: std::time::Instant,
     map_renderer: MapRenderer,
+    chunk_cache: chunk_cache::ChunkCache,
+    ocean: ocean::Ocean,
     key_states: [KeyState; NUM_KEYS],
     mouse_state: MouseState,
+    /// island and tile index of the tile under the mouse
+    hovered_tile: Option<(usize, euclid::default::Point2D<usize>)>,
+    /// world position of the tile shown in the inspector until unpinned
+    pinned_tile: Option<WorldCoordinate>,
+    /// selection being dragged out with the left mouse button
+    drag: Option<Drag>,
+    /// finished selection, handed to the game with the next update
+    selection: Option<Selection>,
 }
 use thiserror::Error;
 #[derive(Error, Debug)]
@@ -55,44 +77,60 @@ impl Renderer {
         style.visuals.dark_mode = true;
         style.visuals.window_shadow.extrusion = 0.0;
         egui_ctx.set_style(style);
-        let egui_engine = egui_allegro_backend::Backend::new(
+        let mut egui_engine = egui_allegro_backend::Backend::new(
             egui_ctx,
             egui_screen_size,
             engine.core.clone(),
             engine.display.clone(),
             engine.primitives_addon.clone(),
         );
-        let mut gui_info = gui::GuiInfo::default();
+        let map_renderer = MapRenderer::new(&engine.core);
+        let mut gui_info = gui::GuiInfo {
+            // make game sprites available to the GUI
+            textures: engine
+                .bitmaps
+                .iter()
+                .map(|bitmap| egui_engine.register_user_texture(bitmap.clone()))
+                .collect(),
+            map: gui::MapInfo {
+                texture: Some(egui_engine.register_user_texture(map_renderer.texture())),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
         let rendered_screen_area = ScreenRect::new(
             ScreenCoordinate::new(0.0, 0.0),
             (init_settings.screen_size - ScreenCoordinate::new(gui_info.min_side_panel_width, 0.0)).to_size(),
         );
         gui_info.rendered_rect = rendered_screen_area;
-        let map_renderer = MapRenderer::new(&*engine.core);
-        let camera_start_pos = WorldCoordinate::new(0.0, 0.0);
-        let s2w = gen_s2w_matrix(init_settings.scale, camera_start_pos);
+        let camera = camera::Camera::new(WorldCoordinate::new(0.0, 0.0), init_settings.scale, init_settings.rotation);
         Ok(Renderer {
             settings: init_settings,
             engine,
             egui_engine,
             mouse: ScreenCoordinate::new(0.0, 0.0),
-            apparent_tile_size: glob::TILE_SIZE * init_settings.scale,
+            mouse_on_display: false,
             rendered_screen_area,
-            screen_on_world: visible_world_rect(rendered_screen_area, s2w),
+            screen_on_world: visible_world_rect(rendered_screen_area, camera.s2w(rendered_screen_area.center())),
+            camera,
             rendered_world_area: WorldRect::default(),
             gui_info,
             last_draw: std::time::Instant::now(),
             map_renderer,
+            chunk_cache: chunk_cache::ChunkCache::default(),
+            ocean: ocean::Ocean::default(),
             key_states: [KeyState::Released; NUM_KEYS],
             mouse_state: MouseState::default(),
+            hovered_tile: None,
+            pinned_tile: None,
+            drag: None,
+            selection: None,
         })
     }
-    const MOUSE_SCALE_FACTOR: f32 = 0.2;
-    const MAX_SCALE: f32 = 7.0;
-    const MIN_SCALE: f32 = 0.2;
+    /// zoom of exported views, 1.0 is full texture resolution
+    const EXPORT_SCALE: f32 = 1.0;
     pub fn next_frame(&mut self, world: &World) -> RendererFeedback {
-        let s2w = gen_s2w_matrix(self.settings.scale, world.screen_pos);
-        self.screen_on_world = visible_world_rect(self.rendered_screen_area, s2w);
+        self.screen_on_world = visible_world_rect(self.rendered_screen_area, self.camera.s2w(self.rendered_screen_area.center()));
         let mut ret = RendererFeedback::default();
         let mut redraw: bool = false;
         loop {
@@ -109,10 +147,23 @@ impl Renderer {
                     ret.exit = true;
                     return ret;
                 }
+                allegro::KeyDown { .. } if self.egui_engine.wants_keyboard_input() => {}
                 allegro::KeyDown { keycode, .. } => {
                     if keycode == allegro::KeyCode::Q {
                         ret.exit = true;
                         return ret;
+                    } else if keycode == allegro::KeyCode::Comma || keycode == allegro::KeyCode::Fullstop {
+                        // turning happens around the screen center, so the camera stays in place
+                        self.camera.rotate(keycode == allegro::KeyCode::Fullstop);
+                        self.apply_settings();
+                    } else if self.egui_engine.modifiers().command
+                        && (keycode == allegro::KeyCode::Z || keycode == allegro::KeyCode::Y)
+                    {
+                        let redo = keycode == allegro::KeyCode::Y || self.egui_engine.modifiers().shift;
+                        self.gui_info.history.push(if redo { HistoryCmd::Redo } else { HistoryCmd::Undo });
+                    } else if keycode == allegro::KeyCode::F12 {
+                        self.gui_info.export_map = true;
+                        self.gui_info.export_view = true;
                     } else if (keycode as usize) < ret.key_states.len() {
                         self.key_states[keycode as usize] = KeyState::Pressed;
                     }
@@ -122,37 +173,47 @@ impl Renderer {
                         self.key_states[keycode as usize] = KeyState::Released;
                     }
                 }
-                allegro::MouseAxes { x, y, dz, .. } => {
+                allegro::MouseAxes { x, y, dx, dy, dz, .. } => {
                     self.mouse = ScreenCoordinate::new(x as f32, y as f32);
-                    if dz != 0 {
-                        self.settings.scale +=
-                            dz as f32 * self.settings.scale * Renderer::MOUSE_SCALE_FACTOR;
-
-                        if self.settings.scale > Renderer::MAX_SCALE {
-                            self.settings.scale = Renderer::MAX_SCALE;
-                        }
-                        if self.settings.scale < Renderer::MIN_SCALE {
-                            self.settings.scale = Renderer::MIN_SCALE;
-                        }
-                        self.apply_settings(world.screen_pos);
+                    self.mouse_on_display = true;
+                    if self.mouse_state.right {
+                        // the world follows the cursor
+                        self.camera.drag(ScreenVector::new(-dx as f32, -dy as f32));
+                    }
+                    if dz != 0 && !self.egui_engine.wants_pointer_input() {
+                        self.camera.zoom(dz, self.mouse, self.rendered_screen_area.center());
                     }
                 }
+                allegro::MouseEnterDisplay { .. } => self.mouse_on_display = true,
+                allegro::MouseLeaveDisplay { .. } => self.mouse_on_display = false,
                 allegro::DisplayResize { width, height, .. } => {
                     self.settings.screen_size = ScreenCoordinate::new(width as f32, height as f32);
                     self.engine
                         .display
                         .acknowledge_resize()
                         .expect("Failed to resize window");
-                    self.apply_settings(world.screen_pos);
+                    self.apply_settings();
                 }
+                // releasing buttons is always forwarded so they do not get stuck when released over the GUI
+                allegro::MouseButtonDown { .. } if self.egui_engine.wants_pointer_input() => {}
                 allegro::MouseButtonDown { button, .. } => match button {
-                    1 => self.mouse_state.left = true,
+                    1 => {
+                        self.mouse_state.left = true;
+                        if self.rendered_screen_area.contains(self.mouse) {
+                            self.drag = Some(Drag::new(self.gui_info.selection_shape, self.mouse, self.mouse_state.pos));
+                        }
+                    }
                     3 => self.mouse_state.middle = true,
                     2 => self.mouse_state.right = true,
                     _ => {}
                 },
                 allegro::MouseButtonUp { button, .. } => match button {
-                    1 => self.mouse_state.left = false,
+                    1 => {
+                        self.mouse_state.left = false;
+                        if let Some(drag) = self.drag.take() {
+                            self.finish_drag(world, drag);
+                        }
+                    }
                     3 => self.mouse_state.middle = false,
                     2 => self.mouse_state.right = false,
                     _ => {}
@@ -161,11 +222,31 @@ impl Renderer {
             }
         }
         if redraw {
-            self.map_renderer.update(world, &*self.engine.core, &*self.engine.display);
-            let s2w = gen_s2w_matrix(self.settings.scale, world.screen_pos);
-            let mouse_in_world = s2w.transform_point(self.mouse);
-            self.mouse_state.pos_diff = self.mouse_state.pos - mouse_in_world;
-            self.mouse_state.pos = mouse_in_world;
+            self.update_camera(world);
+            // the map is also drawn in place of the world when zoomed out
+            if (self.gui_info.show_map || lod::shows_map(self.camera.scale()))
+                && self.map_renderer.update(
+                    world,
+                    self.gui_info.map.layer,
+                    self.gui_info.map.palette,
+                    &self.engine.core,
+                    &self.engine.display,
+                ) {
+                if let Some(texture) = self.gui_info.map.texture {
+                    self.egui_engine.update_user_texture(texture, self.map_renderer.texture());
+                }
+            }
+            // the point on the terrain under the cursor, or on the sea surface
+            let picked = picking::pick(world, &self.world_view(), self.mouse);
+            self.hovered_tile = picked.as_ref().map(|picked| (picked.island, picked.index));
+            self.mouse_state.pos = match picked {
+                Some(picked) => picked.pos,
+                None => self.camera.s2w(self.rendered_screen_area.center()).transform_point(self.mouse),
+            };
+            if let Some(drag) = &mut self.drag {
+                drag.extend(self.mouse, self.mouse_state.pos);
+            }
+            self.mouse_state.drag_start = self.drag.as_ref().filter(|drag| !drag.is_click()).map(Drag::start);
 
             self.draw(world);
             let points = vec![
@@ -184,125 +265,282 @@ impl Renderer {
         ret.key_states = self.key_states;
         ret.mouse = self.mouse_state;
         ret.update_necessary = redraw;
+        ret.tool = self.gui_info.tool;
+        ret.brush_strength = self.gui_info.brush_strength;
+        // the game only looks at feedback that needs an update
+        if redraw {
+            ret.selection = self.selection.take();
+            ret.history = std::mem::take(&mut self.gui_info.history);
+            ret.regenerate_island = self.gui_info.regenerate_island.take();
+            if ret.regenerate_island.is_some() {
+                // show the parameters the island has after the game applied them, or kept on failure
+                self.gui_info.island = None;
+            }
+            self.mouse_state.drag_end = None;
+        }
         ret
     }
 
-    fn apply_settings(&mut self, screen_pos: WorldCoordinate) {
-        self.apparent_tile_size = glob::TILE_SIZE * self.settings.scale;
+    /// turn a released drag into a selection. A click selects the brush around the cursor
+    fn finish_drag(&mut self, world: &World, drag: Drag) {
+        let selection = if drag.is_click() {
+            self.pinned_tile = Some(self.mouse_state.pos);
+            self.gui_info.brush.selection(self.mouse_state.pos)
+        } else {
+            self.mouse_state.drag_end = Some(self.mouse_state.pos);
+            drag.selection()
+        };
+        // e.g. a lasso that was not drawn around anything
+        if selection.is_empty() {
+            return;
+        }
+        self.gui_info.selection = Some(gui::SelectionInfo::new(world, &selection));
+        self.selection = Some(selection);
+    }
+
+    fn apply_settings(&mut self) {
         self.rendered_screen_area = ScreenRect::new(
             ScreenCoordinate::new(0.0, 0.0),
             (self.settings.screen_size - ScreenCoordinate::new(self.gui_info.min_side_panel_width, 0.0)).to_size(),
         );
-        let s2w = gen_s2w_matrix(self.settings.scale, screen_pos);
-        self.screen_on_world = visible_world_rect(self.rendered_screen_area, s2w);
+        self.screen_on_world = visible_world_rect(self.rendered_screen_area, self.camera.s2w(self.rendered_screen_area.center()));
+    }
+
+    /// scroll with the keyboard and the screen edges, then advance the camera easing
+    fn update_camera(&mut self, world: &World) {
+        let dt = self.last_draw.elapsed().as_secs_f32();
+        let pressed = |key: allegro::KeyCode| self.key_states[key as usize] == KeyState::Pressed;
+        let mut direction = ScreenVector::new(0.0, 0.0);
+        if pressed(allegro::KeyCode::W) {
+            direction.y -= 1.0;
+        }
+        if pressed(allegro::KeyCode::A) {
+            direction.x -= 1.0;
+        }
+        if pressed(allegro::KeyCode::S) {
+            direction.y += 1.0;
+        }
+        if pressed(allegro::KeyCode::D) {
+            direction.x += 1.0;
+        }
+        if self.mouse_on_display && !self.mouse_state.right && !self.egui_engine.wants_pointer_input() {
+            direction += camera::edge_scroll_direction(self.rendered_screen_area, self.mouse);
+        }
+        self.camera.scroll_towards(direction, dt);
+        // the clipping rect covers all generated chunks once there are any
+        self.camera.set_bounds((!world.chunks.is_empty()).then_some(world.clipping_rect));
+        self.camera.update(dt);
+        self.apply_settings();
     }
 
     fn draw(&mut self, world: &World) {
         let elapsed = self.last_draw.elapsed();
         self.last_draw = std::time::Instant::now();
         self.gui_info.fps = 1.0 / elapsed.as_secs_f32();
-        self.engine
-            .core
-            .clear_to_color(allegro::Color::from_rgb_f(0.0, 0.0, 0.0));
-        if self.gui_info.show_map {
-            self.map_renderer.draw(&*self.engine.core, self.rendered_screen_area.origin, world);
-            self.gui_info.drawn_tiles = 0;
-        } else {
-            self.engine.core.hold_bitmap_drawing(true);
-            self.gui_info.drawn_tiles = self.draw_world(world);
-            self.engine.core.hold_bitmap_drawing(false);
-        }
+        self.gui_info.drawn_tiles = self.draw_world(world);
+        let w2s = self.camera.w2s(self.rendered_screen_area.center());
+        overlay::draw(
+            &self.gui_info.overlays,
+            &self.engine,
+            world,
+            &w2s,
+            self.rendered_world_area,
+            self.screen_on_world,
+        );
         self.gui_info.mouse_pos = self.mouse_state.pos;
         self.gui_info.rendered_rect = self.rendered_screen_area;
+        self.gui_info.hovered_tile = Some(gui::TileInfo::new(world, self.mouse_state.pos));
+        self.gui_info.pinned_tile = self.pinned_tile.map(|pos| gui::TileInfo::new(world, pos));
+        // edited parameters are kept until another island is pinned
+        let pinned_island = self.gui_info.pinned_tile.as_ref().and_then(|tile| tile.island);
+        if pinned_island != self.gui_info.island.as_ref().map(|island| island.index) {
+            self.gui_info.island = pinned_island.map(|index| gui::IslandInfo {
+                index,
+                params: world.islands[index].params,
+                recipe: world.recipe.clone(),
+            });
+        }
+        let s2w = self.camera.s2w(self.rendered_screen_area.center());
+        self.gui_info.map.area = self.map_renderer.area();
+        if !self.gui_info.custom_export_area {
+            self.gui_info.export_area = self.screen_on_world;
+        }
+        self.gui_info.map.viewport = rect_corners(&self.rendered_screen_area).map(|corner| s2w.transform_point(corner));
         self.egui_engine.draw(gui::draw_gui, &mut self.gui_info);
         self.rendered_screen_area = self.gui_info.rendered_rect; // copy back user values
-        let s2w = gen_s2w_matrix(self.settings.scale, world.screen_pos);
-        self.screen_on_world = visible_world_rect(self.rendered_screen_area, s2w);
+        if self.gui_info.pinned_tile.is_none() {
+            self.pinned_tile = None;
+        }
+        if let Some(target) = self.gui_info.map.clicked.take() {
+            self.camera.move_to(target);
+        }
+        if self.gui_info.export_map || self.gui_info.export_view {
+            self.export(world);
+        }
+        if self.gui_info.save_world {
+            self.save_world(world);
+        }
+        self.screen_on_world = visible_world_rect(self.rendered_screen_area, self.camera.s2w(self.rendered_screen_area.center()));
         self.engine.core.flip_display();
     }
-    fn draw_world(&self, world: &World) -> usize {
-        let mut drawn_cells = 0;
-        let flags = allegro::core::FLIP_NONE;
-        let w2s = gen_w2s_matrix(self.settings.scale, world.screen_pos);
-        for island in &world.islands {
-            if !island.clipping_rect.intersects(&self.rendered_world_area) {
-                log::trace!(
-                    "Island rect {:?} does not intersect rendered area {:?}",
-                    island.clipping_rect,
-                    self.rendered_world_area
-                );
-                continue;
+    /// write images requested by the GUI to the working directory
+    fn export(&mut self, world: &World) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let mut saved: Vec<String> = Vec::new();
+        let mut result = Ok(());
+        if self.gui_info.export_map {
+            self.map_renderer.render(
+                world,
+                self.gui_info.map.layer,
+                self.gui_info.map.palette,
+                &self.engine.core,
+            );
+            let path = format!("map_{}.png", timestamp);
+            result = export::save_bitmap(self.map_renderer.map(), &path);
+            saved.push(path);
+        }
+        if self.gui_info.export_view && result.is_ok() {
+            self.engine.core.set_new_bitmap_flags(allegro::VIDEO_BITMAP);
+            let path = format!("view_{}.png", timestamp);
+            result = export::render_world(
+                &self.engine.core,
+                &self.engine.primitives_addon,
+                &self.engine.bitmaps,
+                &self.engine.transitions,
+                world,
+                self.gui_info.export_area,
+                Renderer::EXPORT_SCALE,
+                self.camera.rotation(),
+            ).and_then(|image| export::save_bitmap(&image, &path));
+            saved.push(path);
+        }
+        self.engine.core.set_target_bitmap(Some(self.engine.display.get_backbuffer()));
+        self.gui_info.export_status = match result {
+            Ok(()) => format!("Saved {}", saved.join(", ")),
+            Err(e) => {
+                log::error!("Export failed: {}", e);
+                e.to_string()
             }
-            for tile_col in &island.tiles {
-                for tile in tile_col {
-                    let tile_screen_pos = w2s.transform_point(tile.pos);
-                    let tile_screen_base = tile_screen_pos - ScreenCoordinate::new(
-                        128.0 * self.settings.scale / 2.0,
-                        128.0 * self.settings.scale / 4.0,
-                    );
-                    // skip if tile is out of screen
-                    if tile_screen_base.x < self.rendered_screen_area.min_x() - 2.0 * self.apparent_tile_size.x
-                        || tile_screen_base.y < self.rendered_screen_area.min_y() - 4.0 * self.apparent_tile_size.y
-                        || tile_screen_base.x >= self.rendered_screen_area.max_x()
-                        || tile_screen_base.y >= self.rendered_screen_area.max_y()
-                    {
-                        continue;
-                    }
-                    let bitmap = if tile.height <= 0.0 {
-                        continue
-                    }
-                    else {
-                        engine::TextureType::Tile
-                    };
-                    self.engine.core.draw_tinted_scaled_rotated_bitmap_region(
-                        &self.engine.bitmaps[bitmap as usize],
-                        // texture start
-                        0.0,
-                        0.0,
-                        // texture dimensions
-                        128.0,
-                        128.0,
-                        allegro::Color::from_rgb_f(1.0, 1.0, 1.0),
-                        0.0,
-                        0.0,
-                        // position
-                        tile_screen_base.x,
-                        tile_screen_base.y,
-                        self.settings.scale,
-                        self.settings.scale,
-                        0.0,
-                        flags,
-                    );
-                    drawn_cells += 1;
-                    if self.mouse_state.pos.x > tile.pos.x
-                        && self.mouse_state.pos.x < (tile.pos.x + 1.0)
-                        && self.mouse_state.pos.y > tile.pos.y
-                        && self.mouse_state.pos.y < (tile.pos.y + 1.0)
-                    {
-                        self.engine.core.draw_tinted_scaled_rotated_bitmap_region(
-                            &self.engine.bitmaps[engine::TextureType::FocusedGreen as usize],
-                            // texture start
-                            0.0,
-                            0.0,
-                            // texture dimensions
-                            glob::TILE_TEXTURE_SIZE.x,
-                            glob::TILE_TEXTURE_SIZE.y,
-                            allegro::Color::from_rgb_f(1.0, 1.0, 1.0),
-                            0.0,
-                            0.0,
-                            // position
-                            tile_screen_base.x,
-                            tile_screen_base.y,
-                            self.settings.scale,
-                            self.settings.scale * glob::PERSPECTIVE_DISTORTION_Y,
-                            0.0,
-                            flags,
-                        );
-                        drawn_cells += 1;
-                    }
+        };
+        self.gui_info.export_map = false;
+        self.gui_info.export_view = false;
+    }
+
+    /// write the world to the working directory
+    fn save_world(&mut self, world: &World) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let path = format!("world_{}.yaml", timestamp);
+        self.gui_info.export_status = match world.save(&path) {
+            Ok(()) => format!("Saved {}", path),
+            Err(e) => {
+                log::error!("Saving world failed: {}", e);
+                e.to_string()
+            }
+        };
+        self.gui_info.save_world = false;
+    }
+
+    /// draw the map in isometric view in place of the terrain
+    fn draw_map_tier(&self, view: &terrain::View, opacity: f32) {
+        view.draw_iso_bitmap(
+            &self.engine.core,
+            &self.map_renderer.texture(),
+            allegro::Color::from_rgba_f(opacity, opacity, opacity, opacity),
+            self.map_renderer.area().origin,
+        );
+    }
+
+    /// view of the world through the camera
+    fn world_view(&self) -> terrain::View {
+        terrain::View {
+            w2s: self.camera.w2s(self.rendered_screen_area.center()),
+            world_area: self.rendered_world_area.inflate(terrain::ELEVATION_MARGIN, terrain::ELEVATION_MARGIN),
+            screen_area: self.rendered_screen_area,
+            scale: self.camera.scale(),
+            rotation: self.camera.rotation(),
+        }
+    }
+
+    fn draw_world(&mut self, world: &World) -> usize {
+        let view = self.world_view();
+        self.ocean.draw(
+            &self.engine.core,
+            &self.engine.primitives_addon,
+            &self.engine.bitmaps[engine::TextureType::Water as usize],
+            world,
+            &view,
+            self.camera.scale() >= lod::FLAT_SCALE,
+        );
+        let mut drawn_cells = 0;
+        for (tier, opacity) in lod::tiers(self.camera.scale()) {
+            match tier {
+                lod::Lod::Terrain(detail) => {
+                    drawn_cells += self.chunk_cache.draw(&self.engine, world, &view, detail, opacity);
                 }
+                lod::Lod::Map => self.draw_map_tier(&view, opacity),
             }
         }
+        self.chunk_cache.end_frame();
+
+        drawn_cells += self.draw_selection(world, &view);
         drawn_cells
     }
+
+    /// highlight the dragged selection or the brush under the mouse, red where the tool cannot act
+    fn draw_selection(&self, world: &World, view: &terrain::View) -> usize {
+        let selection = match &self.drag {
+            Some(drag) if !drag.is_click() => drag.selection(),
+            _ if self.hovered_tile.is_some() && !self.egui_engine.wants_pointer_input() => {
+                self.gui_info.brush.selection(self.mouse_state.pos)
+            }
+            _ => return 0,
+        };
+        let mut tiles: Vec<selection::TilePos> = selection
+            .tiles()
+            .filter(|pos| view.world_area.contains(selection::tile_center(*pos)))
+            .collect();
+        // nearer highlights cover the ones behind them
+        tiles.sort_by(|a, b| {
+            let a = view.rotation.depth(selection::tile_center(*a).to_vector());
+            let b = view.rotation.depth(selection::tile_center(*b).to_vector());
+            a.total_cmp(&b)
+        });
+        self.engine.core.hold_bitmap_drawing(true);
+        for pos in &tiles {
+            let texture = if self.gui_info.tool.is_valid(world, *pos) {
+                engine::TextureType::FocusedGreen
+            } else {
+                engine::TextureType::FocusedRed
+            };
+            let level = world.tile(selection::tile_center(*pos)).map_or(0, |tile| tile.level());
+            view.draw_diamond(
+                &self.engine.core,
+                &self.engine.bitmaps[texture as usize],
+                0,
+                WorldCoordinate::new(pos.x as f32, pos.y as f32),
+                level,
+            );
+        }
+        self.engine.core.hold_bitmap_drawing(false);
+        if let Some(drag) = self.drag.as_ref().filter(|drag| drag.shape == selection::SelectionShape::Lasso) {
+            let points: Vec<ScreenCoordinate> = drag.path().iter().map(|pos| view.w2s.transform_point(*pos)).collect();
+            for line in points.windows(2) {
+                self.engine.primitives_addon.draw_line(
+                    line[0].x,
+                    line[0].y,
+                    line[1].x,
+                    line[1].y,
+                    allegro::Color::from_rgb(255, 255, 255),
+                    1.0,
+                );
+            }
+        }
+        tiles.len()
+    }
 }
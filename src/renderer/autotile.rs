@@ -0,0 +1,183 @@
+//! Autotiling of terrain transitions
+//!
+//! A tile bordering on a texture that blends onto its own gets transition sprites of that texture
+//! drawn on top. Which sprites are picked depends on which of the eight neighbours have the
+//! texture. Transition sets are defined in `TRANSITIONS_PATH`.
+use super::engine;
+use allegro::BitmapLike;
+use serde::Deserialize;
+use std::rc::Rc;
+use thiserror::Error;
+
+pub const TRANSITIONS_PATH: &str = "textures/terrain/transitions.yaml";
+
+/// Offsets of the neighbours in mask bit order, clockwise starting at -y
+pub const NEIGHBOURS: [(isize, isize); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+/// Edge width of generated sprites in tiles
+const DEFAULT_WIDTH: f32 = 0.3;
+/// Sprites per set: edges, outer corners and inner corners
+const NUM_SPRITES: usize = 12;
+/// Mask bit of the diagonal neighbour and of the two edge neighbours next to each corner
+const CORNERS: [(usize, [usize; 2]); 4] = [(7, [6, 0]), (1, [0, 2]), (3, [2, 4]), (5, [4, 6])];
+/// Vertex of the tile at each corner in tile coordinates
+const CORNER_VERTICES: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+
+#[derive(Error, Debug)]
+pub enum AutotileError {
+    #[error("Failed to read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Failed to parse transitions: {0}")]
+    Parse(serde_yaml::Error),
+    #[error("Unknown texture {0}")]
+    UnknownTexture(String),
+    #[error("Failed to load transition sheet {0}")]
+    LoadSheet(String),
+    #[error("Failed to create transition sprites for {0}")]
+    CreateBitmap(String),
+}
+
+/// Entry of the transitions file
+#[derive(Deserialize)]
+struct TransitionDef {
+    texture: String,
+    over: Vec<String>,
+    width: Option<f32>,
+    sheet: Option<String>,
+}
+
+/// Transition sprites of one texture
+pub struct TransitionSet {
+    /// texture blending onto its neighbours, as index into the engine bitmaps
+    pub texture: usize,
+    /// textures it blends onto
+    pub over: Vec<usize>,
+    /// strip of `NUM_SPRITES` square diamond sprites
+    pub sprites: allegro::Bitmap,
+}
+
+/// All transition sets in drawing order
+pub struct Transitions {
+    pub sets: Vec<TransitionSet>,
+}
+
+impl Transitions {
+    /// Load the sets from `path`, generating missing sprite sheets from `bitmaps`
+    ///
+    /// Changes the target bitmap
+    pub fn load(allegro_core: &allegro::Core, bitmaps: &[Rc<allegro::Bitmap>], path: &str) -> Result<Self, AutotileError> {
+        let content = std::fs::read_to_string(path).map_err(|e| AutotileError::Read(path.to_string(), e))?;
+        let defs: Vec<TransitionDef> = serde_yaml::from_str(&content).map_err(AutotileError::Parse)?;
+        let texture_index = |name: &String| {
+            engine::texture_index(name).ok_or_else(|| AutotileError::UnknownTexture(name.clone()))
+        };
+        let mut sets = Vec::new();
+        for def in defs {
+            let texture = texture_index(&def.texture)?;
+            let over = def.over.iter().map(texture_index).collect::<Result<Vec<usize>, AutotileError>>()?;
+            let sprites = match &def.sheet {
+                Some(sheet) => allegro::Bitmap::load(allegro_core, sheet)
+                    .map_err(|_| AutotileError::LoadSheet(sheet.clone()))?,
+                None => Transitions::gen_sprites(allegro_core, &bitmaps[texture], def.width.unwrap_or(DEFAULT_WIDTH))
+                    .ok_or_else(|| AutotileError::CreateBitmap(def.texture.clone()))?,
+            };
+            sets.push(TransitionSet { texture, over, sprites });
+        }
+        Ok(Transitions { sets })
+    }
+
+    /// Cut the transition sprites out of the diamond texture `texture` with soft edges `width` tiles wide
+    fn gen_sprites(allegro_core: &allegro::Core, texture: &allegro::Bitmap, width: f32) -> Option<allegro::Bitmap> {
+        let len = texture.get_height();
+        let new_bitmap_flags = allegro_core.get_new_bitmap_flags();
+        // per pixel drawing is only fast in RAM
+        allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
+        let strip = allegro::Bitmap::new(allegro_core, len * NUM_SPRITES as i32, len).ok()?;
+        allegro_core.set_target_bitmap(Some(&strip));
+        allegro_core.clear_to_color(allegro::Color::from_rgba_f(0.0, 0.0, 0.0, 0.0));
+        for sprite in 0..NUM_SPRITES {
+            let x0 = sprite as i32 * len;
+            // copy the texture, then scale it by the sprite alpha
+            allegro_core.set_blender(
+                allegro::core::BlendOperation::Add,
+                allegro::core::BlendMode::One,
+                allegro::core::BlendMode::Zero,
+            );
+            allegro_core.draw_bitmap_region(texture, 0.0, 0.0, len as f32, len as f32, x0 as f32, 0.0, allegro::core::FLIP_NONE);
+            allegro_core.set_blender(
+                allegro::core::BlendOperation::Add,
+                allegro::core::BlendMode::Zero,
+                allegro::core::BlendMode::Alpha,
+            );
+            for py in 0..len {
+                for px in 0..len {
+                    // pixel centre to tile coordinates of the diamond
+                    let x = (px as f32 + 0.5) / len as f32;
+                    let y = (py as f32 + 0.5) / len as f32;
+                    let alpha = sprite_alpha(sprite, x + y - 0.5, y - x + 0.5, width);
+                    allegro_core.draw_pixel(
+                        (x0 + px) as f32 + 0.5,
+                        py as f32 + 0.5,
+                        allegro::Color::from_rgba_f(alpha, alpha, alpha, alpha),
+                    );
+                }
+            }
+        }
+        allegro_core.set_blender(
+            allegro::core::BlendOperation::Add,
+            allegro::core::BlendMode::One,
+            allegro::core::BlendMode::InverseAlpha,
+        );
+        allegro_core.set_new_bitmap_flags(new_bitmap_flags);
+        Some(strip.clone())
+    }
+}
+
+/// Opacity of transition sprite `sprite` at tile coordinates `u`, `v`
+fn sprite_alpha(sprite: usize, u: f32, v: f32, width: f32) -> f32 {
+    let (u, v) = (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+    let alpha = match sprite {
+        // straight edges: fade out with the distance to the edge
+        0..=3 => {
+            let distance = [v, 1.0 - u, 1.0 - v, u][sprite];
+            1.0 - distance / width
+        }
+        // outer corners: fade out with the distance to the vertex
+        4..=7 => {
+            let (cu, cv) = CORNER_VERTICES[sprite - 4];
+            1.0 - (u - cu).hypot(v - cv) / width
+        }
+        // inner corners: round off the concave corner of two straight edges
+        _ => {
+            let (cu, cv) = CORNER_VERTICES[sprite - 8];
+            1.0 - (u - cu).abs() * (v - cv).abs() / (2.0 * width * width)
+        }
+    };
+    alpha.clamp(0.0, 1.0)
+}
+
+/// Sprites to draw for the neighbours in `mask`, in `NEIGHBOURS` bit order
+pub fn sprites(mask: u8) -> impl Iterator<Item = usize> {
+    let set = move |bit: usize| mask & (1 << bit) != 0;
+    let edges = (0..4).filter(move |&edge| set(2 * edge));
+    let corners = CORNERS.iter().enumerate().filter_map(move |(corner, &(diagonal, [a, b]))| {
+        if set(a) && set(b) {
+            Some(8 + corner)
+        }
+        else if set(diagonal) && !set(a) && !set(b) {
+            Some(4 + corner)
+        }
+        else {
+            None
+        }
+    });
+    edges.chain(corners)
+}
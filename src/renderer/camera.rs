@@ -0,0 +1,184 @@
+//! Camera looking at the world
+//!
+//! Position and zoom ease towards their targets, so every movement is smooth. Scrolling speed is
+//! given in screen pixels and therefore the same at every zoom. Zooming keeps the world position
+//! under the cursor in place. The camera centre stays inside the generated part of the world,
+//! which grows as the view reaches past its edge.
+use crate::glob::types::*;
+
+/// Zoom change per mouse wheel step, relative to the current zoom
+const ZOOM_STEP: f32 = 0.2;
+const MAX_SCALE: f32 = 7.0;
+const MIN_SCALE: f32 = 0.2;
+/// Keyboard and edge scrolling speed in screen pixels per second
+const SCROLL_SPEED: f32 = 900.0;
+/// Distance from the edge of the rendered area in pixels at which the view scrolls
+const EDGE_SCROLL_MARGIN: f32 = 10.0;
+/// Fraction of the remaining way to the target covered per second is `1 - exp(-EASING_RATE)`
+const EASING_RATE: f32 = 12.0;
+/// Relative zoom difference below which the zoom snaps to its target
+const ZOOM_EPSILON: f32 = 1e-3;
+
+/// World position kept under the cursor while zooming
+#[derive(Clone, Copy)]
+struct ZoomAnchor {
+    world: WorldCoordinate,
+    /// cursor position relative to the screen centre
+    offset: ScreenVector,
+}
+
+pub struct Camera {
+    /// world position shown at the screen centre
+    pos: WorldCoordinate,
+    scale: f32,
+    rotation: Rotation,
+    target_pos: WorldCoordinate,
+    target_scale: f32,
+    zoom_anchor: Option<ZoomAnchor>,
+    /// area the camera position is kept in, unbounded if `None`
+    bounds: Option<WorldRect>,
+}
+
+impl Camera {
+    pub fn new(pos: WorldCoordinate, scale: f32, rotation: Rotation) -> Self {
+        let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        Camera {
+            pos,
+            scale,
+            rotation,
+            target_pos: pos,
+            target_scale: scale,
+            zoom_anchor: None,
+            bounds: None,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Transformation from screen to world coordinates with the camera at `screen_center`
+    pub fn s2w(&self, screen_center: ScreenCoordinate) -> euclid::Transform2D<f32, ScreenSpace, WorldSpace> {
+        gen_s2w_matrix(self.scale, self.pos, screen_center, self.rotation)
+    }
+
+    /// Transformation from world to screen coordinates with the camera at `screen_center`
+    pub fn w2s(&self, screen_center: ScreenCoordinate) -> euclid::Transform2D<f32, WorldSpace, ScreenSpace> {
+        gen_w2s_matrix(self.scale, self.pos, screen_center, self.rotation)
+    }
+
+    /// Zoom in by `steps` wheel steps, out if negative, keeping the world under `cursor` in place
+    pub fn zoom(&mut self, steps: i32, cursor: ScreenCoordinate, screen_center: ScreenCoordinate) {
+        let anchor = ZoomAnchor {
+            world: self.s2w(screen_center).transform_point(cursor),
+            offset: cursor - screen_center,
+        };
+        self.target_scale = (self.target_scale * (1.0 + steps as f32 * ZOOM_STEP)).clamp(MIN_SCALE, MAX_SCALE);
+        self.target_pos = anchor.camera_pos(self.target_scale, self.rotation);
+        self.zoom_anchor = Some(anchor);
+    }
+
+    /// Move the target by `delta` screen pixels
+    pub fn scroll(&mut self, delta: ScreenVector) {
+        self.zoom_anchor = None;
+        self.target_pos += self.screen_to_world(delta);
+    }
+
+    /// Scroll in screen `direction` for `dt` seconds at the scrolling speed
+    pub fn scroll_towards(&mut self, direction: ScreenVector, dt: f32) {
+        if direction != ScreenVector::zero() {
+            self.scroll(direction * SCROLL_SPEED * dt);
+        }
+    }
+
+    /// Move the camera by `delta` screen pixels without easing, so the world sticks to a dragging cursor
+    pub fn drag(&mut self, delta: ScreenVector) {
+        self.zoom_anchor = None;
+        let delta = self.screen_to_world(delta);
+        self.pos += delta;
+        self.target_pos += delta;
+    }
+
+    /// Glide to `target`
+    pub fn move_to(&mut self, target: WorldCoordinate) {
+        self.zoom_anchor = None;
+        self.target_pos = target;
+    }
+
+    /// Turn a quarter around the screen centre
+    pub fn rotate(&mut self, clockwise: bool) {
+        // the anchor offset is on screen and would point elsewhere after turning
+        self.zoom_anchor = None;
+        self.rotation = if clockwise { self.rotation.clockwise() } else { self.rotation.counter_clockwise() };
+    }
+
+    pub fn set_bounds(&mut self, bounds: Option<WorldRect>) {
+        self.bounds = bounds;
+    }
+
+    /// Advance the easing by `dt` seconds
+    pub fn update(&mut self, dt: f32) {
+        let progress = 1.0 - (-EASING_RATE * dt).exp();
+        // zoom eases exponentially, so zooming in and out feel alike
+        self.scale = (self.scale.ln() + (self.target_scale.ln() - self.scale.ln()) * progress).exp();
+        let zoom_done = (self.scale / self.target_scale - 1.0).abs() < ZOOM_EPSILON;
+        if zoom_done {
+            self.scale = self.target_scale;
+        }
+        match self.zoom_anchor {
+            Some(anchor) => {
+                self.pos = anchor.camera_pos(self.scale, self.rotation);
+                if zoom_done {
+                    self.zoom_anchor = None;
+                }
+            }
+            None => self.pos = self.pos.lerp(self.target_pos, progress),
+        }
+        if let Some(bounds) = self.bounds {
+            self.pos = clamp_to(self.pos, bounds);
+            self.target_pos = clamp_to(self.target_pos, bounds);
+        }
+    }
+
+    fn screen_to_world(&self, delta: ScreenVector) -> WorldVector {
+        self.s2w(ScreenCoordinate::zero()).transform_vector(delta)
+    }
+}
+
+impl ZoomAnchor {
+    /// Camera position at zoom `scale` that shows the anchored world position under the cursor
+    fn camera_pos(&self, scale: f32, rotation: Rotation) -> WorldCoordinate {
+        let s2w = gen_s2w_matrix(scale, WorldCoordinate::zero(), ScreenCoordinate::zero(), rotation);
+        self.world - s2w.transform_vector(self.offset)
+    }
+}
+
+/// Direction to scroll in if `mouse` is close to the edge of `area`
+pub fn edge_scroll_direction(area: ScreenRect, mouse: ScreenCoordinate) -> ScreenVector {
+    if !area.contains(mouse) {
+        return ScreenVector::zero();
+    }
+    let axis = |pos: f32, min: f32, max: f32| {
+        if pos < min + EDGE_SCROLL_MARGIN {
+            -1.0
+        }
+        else if pos >= max - EDGE_SCROLL_MARGIN {
+            1.0
+        }
+        else {
+            0.0
+        }
+    };
+    ScreenVector::new(
+        axis(mouse.x, area.min_x(), area.max_x()),
+        axis(mouse.y, area.min_y(), area.max_y()),
+    )
+}
+
+fn clamp_to(pos: WorldCoordinate, bounds: WorldRect) -> WorldCoordinate {
+    pos.clamp(bounds.min(), bounds.max())
+}
@@ -0,0 +1,246 @@
+//! Pre-rendered terrain
+//!
+//! Chunks are too large for a single texture, so the terrain is cached in square blocks of
+//! `BLOCK_SIZE` tiles. Blocks are rendered at the power of two zoom bucket at or above the current
+//! zoom and scaled down when drawn. A block is re-rendered when the revision of its chunk changes.
+//! Every level of detail and camera rotation has its own blocks.
+use super::engine::Engine;
+use super::terrain;
+use crate::glob;
+use crate::glob::types::*;
+use crate::world::{chunk_index, World, CHUNK_SIZE};
+use allegro::BitmapLike;
+use std::collections::HashMap;
+
+/// Edge length of a cached block in tiles. Divides `CHUNK_SIZE`
+const BLOCK_SIZE: f32 = 32.0;
+/// Zoom above which the terrain is drawn directly
+const MAX_CACHED_SCALE: f32 = 1.0;
+/// Blocks rendered per frame. The others are drawn directly until their turn
+const MAX_RENDERS_PER_FRAME: usize = 4;
+/// Frames after which an unused block is dropped
+const MAX_IDLE_FRAMES: u64 = 120;
+
+type BlockIndex = euclid::default::Point2D<isize>;
+
+struct CachedBlock {
+    /// `None` if the block contains no tiles
+    bitmap: Option<allegro::Bitmap>,
+    /// revision of the owning chunk the block was rendered at
+    revision: u64,
+    tiles: usize,
+    last_used: u64,
+}
+
+/// Terrain blocks by block index, zoom bucket exponent, detail and rotation
+#[derive(Default)]
+pub struct ChunkCache {
+    blocks: HashMap<(BlockIndex, i32, terrain::Detail, Rotation), CachedBlock>,
+    frame: u64,
+}
+
+impl ChunkCache {
+    /// Draw the terrain in `view` with `detail` to the backbuffer. Returns the number of drawn tiles
+    ///
+    /// Cached blocks are drawn with `opacity`. Blocks that are not cached yet are drawn directly if
+    /// fully opaque and skipped otherwise.
+    pub fn draw(
+        &mut self,
+        engine: &Engine,
+        world: &World,
+        view: &terrain::View,
+        detail: terrain::Detail,
+        opacity: f32,
+    ) -> usize {
+        if view.scale > MAX_CACHED_SCALE {
+            return ChunkCache::draw_direct(engine, world, view, view.world_area, detail);
+        }
+        let bucket = view.scale.log2().ceil() as i32;
+        let bucket_scale = 2f32.powi(bucket);
+
+        let first = block_index(view.world_area.min());
+        let last = block_index(view.world_area.max());
+        let mut indices: Vec<BlockIndex> = Vec::new();
+        for x in first.x..last.x + 1 {
+            for y in first.y..last.y + 1 {
+                indices.push(BlockIndex::new(x, y));
+            }
+        }
+        // blocks further back first
+        indices.sort_by(|a, b| {
+            let a = view.rotation.depth(block_area(*a).center().to_vector());
+            let b = view.rotation.depth(block_area(*b).center().to_vector());
+            a.total_cmp(&b)
+        });
+
+        let mut drawn_tiles = 0;
+        let mut renders = 0;
+        for ind in indices {
+            let area = block_area(ind);
+            let revision = match world.chunks.get(&chunk_index(area.origin)) {
+                Some(chunk) => chunk.revision,
+                // not generated, so empty
+                None => continue,
+            };
+            // the world area is the bounding box of the screen, so many blocks are off screen
+            let zoom = view.scale / bucket_scale;
+            let dest = view.w2s.transform_point(area.origin) - block_anchor(bucket_scale, view.rotation) * zoom;
+            let on_screen = ScreenRect::new(dest, (block_size(bucket_scale) * zoom).to_size());
+            if !on_screen.intersects(&view.screen_area) {
+                continue;
+            }
+            let key = (ind, bucket, detail, view.rotation);
+            let valid = self.blocks.get(&key).is_some_and(|block| block.revision == revision);
+            if !valid {
+                if renders == MAX_RENDERS_PER_FRAME {
+                    if opacity >= 1.0 {
+                        drawn_tiles += ChunkCache::draw_direct(engine, world, view, area, detail);
+                    }
+                    continue;
+                }
+                renders += 1;
+                let block = ChunkCache::render(engine, world, area, bucket_scale, view.rotation, detail, revision);
+                self.blocks.insert(key, block);
+            }
+            let block = self.blocks.get_mut(&key).unwrap();
+            block.last_used = self.frame;
+            drawn_tiles += block.tiles;
+            if let Some(bitmap) = &block.bitmap {
+                // scale the block from its bucket down to the current zoom
+                engine.core.draw_tinted_scaled_bitmap(
+                    bitmap,
+                    allegro::Color::from_rgba_f(opacity, opacity, opacity, opacity),
+                    0.0,
+                    0.0,
+                    bitmap.get_width() as f32,
+                    bitmap.get_height() as f32,
+                    dest.x,
+                    dest.y,
+                    bitmap.get_width() as f32 * zoom,
+                    bitmap.get_height() as f32 * zoom,
+                    allegro::core::FLIP_NONE,
+                );
+            }
+        }
+        drawn_tiles
+    }
+
+    /// Drop blocks that were not drawn for a while. Call once per frame
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.blocks.retain(|_, block| frame - block.last_used < MAX_IDLE_FRAMES);
+        self.frame += 1;
+    }
+
+    /// Draw the tiles in `area` without the cache
+    fn draw_direct(
+        engine: &Engine,
+        world: &World,
+        view: &terrain::View,
+        area: WorldRect,
+        detail: terrain::Detail,
+    ) -> usize {
+        let view = terrain::View {
+            world_area: area,
+            ..*view
+        };
+        terrain::draw(
+            &engine.core,
+            &engine.primitives_addon,
+            &engine.bitmaps,
+            &engine.transitions,
+            world,
+            &view,
+            detail,
+        )
+    }
+
+    /// Render the block covering `area` at zoom `scale` seen with `rotation`
+    fn render(
+        engine: &Engine,
+        world: &World,
+        area: WorldRect,
+        scale: f32,
+        rotation: Rotation,
+        detail: terrain::Detail,
+        revision: u64,
+    ) -> CachedBlock {
+        let mut block = CachedBlock {
+            bitmap: None,
+            revision,
+            tiles: 0,
+            last_used: 0,
+        };
+        if !world.islands.iter().any(|island| island.clipping_rect.intersects(&area)) {
+            return block;
+        }
+        let anchor = block_anchor(scale, rotation);
+        let size = block_size(scale);
+        engine.core.set_new_bitmap_flags(allegro::VIDEO_BITMAP | allegro::MIN_LINEAR | allegro::MAG_LINEAR);
+        let bitmap = allegro::Bitmap::new(&engine.core, size.x.ceil() as i32, size.y.ceil() as i32);
+        engine.core.set_new_bitmap_flags(allegro::VIDEO_BITMAP);
+        let bitmap = match bitmap {
+            Ok(bitmap) => bitmap,
+            Err(_) => {
+                log::error!("Cannot create terrain cache bitmap of size {:?}", size);
+                return block;
+            }
+        };
+        let view = terrain::View {
+            w2s: gen_w2s_matrix(scale, area.origin, anchor.to_point(), rotation),
+            world_area: area,
+            screen_area: ScreenRect::new(ScreenCoordinate::new(0.0, 0.0), size.to_size()),
+            scale,
+            rotation,
+        };
+        // the caller's target is restored afterwards, the crate offers no getter
+        let target = unsafe { allegro_sys::al_get_target_bitmap() };
+        engine.core.set_target_bitmap(Some(&bitmap));
+        engine.core.clear_to_color(allegro::Color::from_rgba_f(0.0, 0.0, 0.0, 0.0));
+        block.tiles = terrain::draw(
+            &engine.core,
+            &engine.primitives_addon,
+            &engine.bitmaps,
+            &engine.transitions,
+            world,
+            &view,
+            detail,
+        );
+        unsafe { allegro_sys::al_set_target_bitmap(target) };
+        block.bitmap = Some(bitmap);
+        block
+    }
+}
+
+fn block_index(pos: WorldCoordinate) -> BlockIndex {
+    BlockIndex::new((pos.x / BLOCK_SIZE).floor() as isize, (pos.y / BLOCK_SIZE).floor() as isize)
+}
+
+fn block_area(ind: BlockIndex) -> WorldRect {
+    WorldRect::new(
+        WorldCoordinate::new(ind.x as f32 * BLOCK_SIZE, ind.y as f32 * BLOCK_SIZE),
+        WorldVector::new(BLOCK_SIZE, BLOCK_SIZE).to_size(),
+    )
+}
+
+/// Size of the bitmap of a block rendered at zoom `scale`. The same for every rotation
+fn block_size(scale: f32) -> ScreenVector {
+    ScreenVector::new(
+        BLOCK_SIZE * glob::TILE_SIZE.x * scale,
+        BLOCK_SIZE * glob::TILE_SIZE.y * scale + terrain::ELEVATION_MARGIN * glob::TILE_SIZE.y * scale,
+    )
+}
+
+/// Position of the block origin in its bitmap seen with `rotation`. Leaves room above for raised tiles
+fn block_anchor(scale: f32, rotation: Rotation) -> ScreenVector {
+    let w2s = gen_w2s_matrix(scale, WorldCoordinate::new(0.0, 0.0), ScreenCoordinate::new(0.0, 0.0), rotation);
+    let corners = rect_corners(&block_area(BlockIndex::new(0, 0))).map(|corner| w2s.transform_point(corner));
+    let bounds = ScreenRect::from_points(corners);
+    ScreenVector::new(
+        -bounds.min_x(),
+        -bounds.min_y() + terrain::ELEVATION_MARGIN * glob::TILE_SIZE.y * scale,
+    )
+}
+
+// blocks must not span chunks
+const _: () = assert!(CHUNK_SIZE % BLOCK_SIZE == 0.0);
@@ -22,13 +22,22 @@
 //!     );
 //! }
 //! ```
+use allegro::{BitmapLike, Flag};
 use std::rc::Rc;
 
 pub struct Backend {
     egui_ctx: egui::Context,
-    egui_textures: Vec<allegro::Bitmap>,
-    egui_texture_sizes: Vec<(i32, i32)>,
+    /// textures managed by egui, indexed by `egui::TextureId::Managed`
+    egui_textures: std::collections::HashMap<u64, allegro::Bitmap>,
+    /// bitmaps registered by the application, indexed by `egui::TextureId::User`
+    user_textures: std::collections::HashMap<u64, Rc<allegro::Bitmap>>,
+    next_user_texture_id: u64,
     egui_input: egui::RawInput,
+    /// modifier keys currently held down
+    modifiers: egui::Modifiers,
+    /// text copied from egui. The allegro bindings offer no access to the system clipboard,
+    /// so copy and paste only work within the application
+    clipboard: String,
     allegro_core: Rc<allegro::core::Core>,
     allegro_display: Rc<allegro::display::Display>,
     allegro_primitives_addon: Rc<allegro_primitives::PrimitivesAddon>,
@@ -44,15 +53,53 @@ impl Backend {
     ) -> Self {
         Backend {
             egui_ctx,
-            egui_textures: Vec::new(),
-            egui_texture_sizes: Vec::new(),
+            egui_textures: std::collections::HashMap::new(),
+            user_textures: std::collections::HashMap::new(),
+            next_user_texture_id: 0,
             egui_input: egui::RawInput { screen_rect: Some(screen_size), ..Default::default() },
+            modifiers: egui::Modifiers::default(),
+            clipboard: String::new(),
             allegro_core,
             allegro_display,
             allegro_primitives_addon,
         }
     }
 
+    /// Make `bitmap` drawable by egui, e.g. with `egui::Ui::image`
+    pub fn register_user_texture(&mut self, bitmap: Rc<allegro::Bitmap>) -> egui::TextureId {
+        let id = self.next_user_texture_id;
+        self.next_user_texture_id += 1;
+        self.user_textures.insert(id, bitmap);
+        egui::TextureId::User(id)
+    }
+
+    /// true if the mouse is over a GUI element or egui is using it, e.g. while dragging a slider.
+    /// Mouse input should not reach the world then
+    pub fn wants_pointer_input(&self) -> bool {
+        self.egui_ctx.is_pointer_over_area() || self.egui_ctx.wants_pointer_input()
+    }
+
+    /// true if a GUI element has keyboard focus, e.g. a text field.
+    /// Keyboard input should not reach the world then
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.egui_ctx.wants_keyboard_input()
+    }
+
+    /// modifier keys currently held down
+    pub fn modifiers(&self) -> egui::Modifiers {
+        self.modifiers
+    }
+
+    /// Replace the bitmap behind a registered user texture
+    pub fn update_user_texture(&mut self, texture_id: egui::TextureId, bitmap: Rc<allegro::Bitmap>) {
+        match texture_id {
+            egui::TextureId::User(id) if self.user_textures.contains_key(&id) => {
+                self.user_textures.insert(id, bitmap);
+            }
+            _ => log::error!("Cannot update unknown user texture {:?}", texture_id),
+        }
+    }
+
     pub fn handle_allegro_event(&mut self, event: &allegro::Event) {
         match event {
             allegro::TimerTick { .. } => {}
@@ -61,8 +108,8 @@ impl Backend {
             } => {
                 if *dz != 0 {
                     self.egui_input.events.push(egui::Event::Scroll(egui::Vec2 {
-                        x: *dz as f32,
-                        y: 0.0,
+                        x: 0.0,
+                        y: *dz as f32 * Backend::SCROLL_STEP,
                     }))
                 }
                 if *dx != 0 || *dy != 0 {
@@ -89,13 +136,7 @@ impl Backend {
                     pos,
                     button: egui_button,
                     pressed: true,
-                    modifiers: egui::Modifiers {
-                        alt: false,
-                        ctrl: false,
-                        shift: false,
-                        mac_cmd: false,
-                        command: false,
-                    },
+                    modifiers: self.modifiers,
                 });
             }
             allegro::MouseButtonUp { x, y, button, .. } => {
@@ -113,15 +154,56 @@ impl Backend {
                     pos,
                     button: egui_button,
                     pressed: false,
-                    modifiers: egui::Modifiers {
-                        alt: false,
-                        ctrl: false,
-                        shift: false,
-                        mac_cmd: false,
-                        command: false,
-                    },
+                    modifiers: self.modifiers,
                 });
             }
+            allegro::KeyDown { keycode, .. } => {
+                self.update_modifiers(*keycode, true);
+                if let Some(key) = translate_key(*keycode) {
+                    self.egui_input.events.push(egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers: self.modifiers,
+                    });
+                }
+            }
+            allegro::KeyUp { keycode, .. } => {
+                self.update_modifiers(*keycode, false);
+                if let Some(key) = translate_key(*keycode) {
+                    self.egui_input.events.push(egui::Event::Key {
+                        key,
+                        pressed: false,
+                        modifiers: self.modifiers,
+                    });
+                }
+            }
+            allegro::KeyChar {
+                keycode, unichar, repeat, ..
+            } => {
+                // the initial press is already forwarded by `KeyDown`, only held keys repeat here
+                if *repeat {
+                    if let Some(key) = translate_key(*keycode) {
+                        self.egui_input.events.push(egui::Event::Key {
+                            key,
+                            pressed: true,
+                            modifiers: self.modifiers,
+                        });
+                    }
+                }
+                if self.modifiers.command {
+                    match keycode {
+                        allegro::KeyCode::C => self.egui_input.events.push(egui::Event::Copy),
+                        allegro::KeyCode::X => self.egui_input.events.push(egui::Event::Cut),
+                        allegro::KeyCode::V => self
+                            .egui_input
+                            .events
+                            .push(egui::Event::Paste(self.clipboard.clone())),
+                        _ => {}
+                    }
+                } else if !unichar.is_control() {
+                    self.egui_input.events.push(egui::Event::Text(unichar.to_string()));
+                }
+            }
             allegro::DisplayResize { width, height, .. } => {
                 self.egui_input.screen_rect = Some(egui::Rect {
                     min: egui::Pos2 { x: 0.0, y: 0.0 },
@@ -141,7 +223,10 @@ impl Backend {
             gui(ctx, gui_args);
         });
         self.egui_input.events.clear();
-        self.upload_egui_textures(output.textures_delta);
+        if !output.platform_output.copied_text.is_empty() {
+            self.clipboard = output.platform_output.copied_text;
+        }
+        self.upload_egui_textures(output.textures_delta.set);
 
         // create triangles to paint
         let clipped_primitives = self.egui_ctx.tessellate(output.shapes);
@@ -164,23 +249,23 @@ impl Backend {
             // + a set of associated indices
             // + a texture id
 
-            let texture_id: usize;
-            match mesh.texture_id {
-                egui::TextureId::Managed(id) => {
-                    if (id as usize) >= self.egui_textures.len()
-                        || (id as usize >= self.egui_texture_sizes.len()) {
-                            log::error!("Requested TextureId out of range");
-                            continue;
-                        }
-                    else {
-                        texture_id = id as usize;
+            let texture = match mesh.texture_id {
+                egui::TextureId::Managed(id) => match self.egui_textures.get(&id) {
+                    Some(bitmap) => bitmap,
+                    None => {
+                        log::error!("Requested unknown managed TextureId {}", id);
+                        continue;
                     }
                 },
-                egui::TextureId::User(_) => {
-                    log::warn!("Unsupported: TextureId of type 'User'");
-                    continue;
+                egui::TextureId::User(id) => match self.user_textures.get(&id) {
+                    Some(bitmap) => &**bitmap,
+                    None => {
+                        log::error!("Requested unknown user TextureId {}", id);
+                        continue;
+                    }
                 },
-            }
+            };
+            let texture_size = (texture.get_width(), texture.get_height());
             // convert egui-vertices to allegro-vertices
             let vertex_buffer = VertexBuffer {
                 data: mesh
@@ -188,8 +273,8 @@ impl Backend {
                     .iter()
                     .map(|vert| {
                         let col = vert.color.to_array();
-                        let mut u = vert.uv.x * self.egui_texture_sizes[texture_id].0 as f32;
-                        let mut v = vert.uv.y * self.egui_texture_sizes[texture_id].1 as f32;
+                        let mut u = vert.uv.x * texture_size.0 as f32;
+                        let mut v = vert.uv.y * texture_size.1 as f32;
                         let color = allegro::Color::from_rgba(
                                  col[0], col[1], col[2], col[3]);
                         // for some reason the point (0.0, 0.0) needs offset
@@ -224,7 +309,7 @@ impl Backend {
             self.allegro_primitives_addon
                 .draw_indexed_prim::<VertexBuffer, allegro::Bitmap>(
                     &vertex_buffer,
-                    Some(&self.egui_textures[texture_id]),
+                    Some(texture),
                     &indices[..],
                     0,
                     indices.len() as u32,
@@ -244,103 +329,199 @@ impl Backend {
             allegro::core::BlendMode::One,
             allegro::core::BlendMode::InverseAlpha,
         );
-    }
-
-    const GAMMA: f32 = 1.0;
 
-    /// convert egui texture to `allegro::Bitmap`
-    fn upload_egui_textures(&mut self, textures_delta: egui::TexturesDelta) {
-        if textures_delta.is_empty() {
-            return;
-        }
-        // iterate obsolete textures and remove them
-        for _ in textures_delta.free {
-            log::debug!("Unsupported: Free'ing textures");
-        }
-        // iterate new textures and apply updates
-        for (texture_id, image_delta) in textures_delta.set {
-            if image_delta.filter != epaint::textures::TextureFilter::Linear {
-                log::warn!("Unsupported: Filter {:?}", image_delta.filter);
-                continue;
-            }
-            self.allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP | allegro::MAG_LINEAR);
-            let font_image: epaint::image::FontImage;
-            if let epaint::image::ImageData::Font(image) = image_delta.image {
-                font_image = image;
-            }
-            else {
-                log::warn!("Unsupported: ImageData of type 'Color'");
-                continue;
-            }
-            if image_delta.pos.is_some() {
-                log::warn!("Unsupported: partial texture update");
-                continue;
-            }
-            let tex = match allegro::Bitmap::new(
-                &self.allegro_core,
-                font_image.width() as i32,
-                font_image.height() as i32,
-            ) {
-                Err(_) => {
-                    log::error!("Cannot create allegro bitmap: allegro::Bitmap::new() failed");
-                    continue;
+        // textures may only be freed once this frame is painted
+        for texture_id in output.textures_delta.free {
+            match texture_id {
+                egui::TextureId::Managed(id) => {
+                    if self.egui_textures.remove(&id).is_none() {
+                        log::warn!("Cannot free unknown managed TextureId {}", id);
+                    }
                 }
-                Ok(t) => t,
-            };
+                egui::TextureId::User(id) => log::warn!("User TextureId {} is not managed by egui", id),
+            }
+        }
+    }
 
-            let iter = font_image.srgba_pixels(Backend::GAMMA);
+    /// track state of modifier keys
+    fn update_modifiers(&mut self, keycode: allegro::KeyCode, pressed: bool) {
+        match keycode {
+            allegro::KeyCode::LShift | allegro::KeyCode::RShift => self.modifiers.shift = pressed,
+            allegro::KeyCode::LCtrl | allegro::KeyCode::RCtrl => self.modifiers.ctrl = pressed,
+            allegro::KeyCode::Alt | allegro::KeyCode::AltGr => self.modifiers.alt = pressed,
+            allegro::KeyCode::Command => self.modifiers.mac_cmd = pressed,
+            _ => return,
+        }
+        self.modifiers.command = self.modifiers.ctrl || self.modifiers.mac_cmd;
+        self.egui_input.modifiers = self.modifiers;
+    }
 
-            let pixels: Vec<allegro::Color> = iter
-                .map(|x| {
-                    let color = x.to_array();
-                    allegro::Color::from_rgba(color[0], color[1], color[2], color[3])
-                })
-                .collect();
-            let mut iter = pixels.iter();
-            self.allegro_core.set_target_bitmap(Some(&tex));
-            for y in 0..font_image.height() as i32 {
-                for x in 0..font_image.width() as i32 {
-                    let color = *iter.next().unwrap();
-                    self.allegro_core.put_pixel(x, y, color);
-                }
-            }
-            self.allegro_core.set_target_bitmap(Some(self.allegro_display.get_backbuffer()));
-            self.allegro_core.set_new_bitmap_flags(
-                allegro::VIDEO_BITMAP | allegro::MAG_LINEAR
-            );
-            self.allegro_core.set_new_bitmap_format(allegro::color::PixelFormat::PixelFormatAbgr8888Le);
+    const GAMMA: f32 = 1.0;
+    /// points scrolled per mouse wheel notch
+    const SCROLL_STEP: f32 = 50.0;
 
-            // resolve TextureID and insert bitmap to struct
-            if let egui::TextureId::Managed(id) = texture_id {
-                if (id as usize) < self.egui_textures.len() {
-                    log::warn!("Texture ID already exists. Skipping update");
+    /// create, replace or partially update textures managed by egui
+    fn upload_egui_textures(&mut self, textures: Vec<(egui::TextureId, epaint::ImageDelta)>) {
+        for (texture_id, image_delta) in textures {
+            let id = match texture_id {
+                egui::TextureId::Managed(id) => id,
+                egui::TextureId::User(id) => {
+                    log::warn!("User TextureId {} is not managed by egui", id);
                     continue;
                 }
-                else if (id as usize) != self.egui_textures.len() {
-                    // for this we need to store textures inside a HashMap instead of vector
-                    panic!("Texture IDs have gaps, egui backend cannot handle this");
-                }
-                else {
-                    match self.allegro_display.convert_bitmap(&tex) {
-                        Ok(allegro_bitmap) => self.egui_textures.push(allegro_bitmap),
-                        Err(_) => {
-                            log::error!("Failed to convert egui bitmap to allegro bitmap");
-                            continue;
-                        },
+            };
+            let image = match self.image_to_bitmap(&image_delta.image) {
+                Some(i) => i,
+                None => continue,
+            };
+            match image_delta.pos {
+                None => {
+                    let filter_flags = match image_delta.filter {
+                        epaint::textures::TextureFilter::Linear => allegro::MIN_LINEAR | allegro::MAG_LINEAR,
+                        epaint::textures::TextureFilter::Nearest => allegro::BitmapFlags::zero(),
+                    };
+                    self.allegro_core.set_new_bitmap_flags(allegro::VIDEO_BITMAP | filter_flags);
+                    match self.allegro_display.convert_bitmap(&image) {
+                        Ok(allegro_bitmap) => {
+                            self.egui_textures.insert(id, allegro_bitmap);
+                        }
+                        Err(_) => log::error!("Failed to convert egui bitmap to allegro bitmap"),
                     }
-                    self.egui_texture_sizes.push((
-                        font_image.width() as i32,
-                        font_image.height() as i32,
-                    ));
                 }
+                Some(pos) => match self.egui_textures.get(&id) {
+                    Some(texture) => {
+                        // overwrite the sub-rect without blending
+                        self.allegro_core.set_target_bitmap(Some(texture));
+                        self.allegro_core.set_blender(
+                            allegro::core::BlendOperation::Add,
+                            allegro::core::BlendMode::One,
+                            allegro::core::BlendMode::Zero,
+                        );
+                        self.allegro_core.draw_bitmap(
+                            &image,
+                            pos[0] as f32,
+                            pos[1] as f32,
+                            allegro::core::FLIP_NONE,
+                        );
+                        self.allegro_core.set_blender(
+                            allegro::core::BlendOperation::Add,
+                            allegro::core::BlendMode::One,
+                            allegro::core::BlendMode::InverseAlpha,
+                        );
+                        self.allegro_core.set_target_bitmap(Some(self.allegro_display.get_backbuffer()));
+                    }
+                    None => log::error!("Partial update of unknown managed TextureId {}", id),
+                },
             }
-            else {
-                log::warn!("Unsupported: TextureId of type 'user");
+        }
+        self.allegro_core.set_new_bitmap_flags(allegro::VIDEO_BITMAP | allegro::MAG_LINEAR);
+        self.allegro_core.set_new_bitmap_format(allegro::color::PixelFormat::PixelFormatAbgr8888Le);
+    }
+
+    /// convert egui image to an `allegro::Bitmap` in RAM
+    fn image_to_bitmap(&self, image: &epaint::ImageData) -> Option<allegro::Bitmap> {
+        self.allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
+        let bitmap = match allegro::Bitmap::new(
+            &self.allegro_core,
+            image.width() as i32,
+            image.height() as i32,
+        ) {
+            Err(_) => {
+                log::error!("Cannot create allegro bitmap: allegro::Bitmap::new() failed");
+                return None;
             }
+            Ok(b) => b,
+        };
+        let pixels: Vec<egui::Color32> = match image {
+            epaint::ImageData::Color(color_image) => color_image.pixels.clone(),
+            epaint::ImageData::Font(font_image) => font_image.srgba_pixels(Backend::GAMMA).collect(),
+        };
+        self.allegro_core.set_target_bitmap(Some(&bitmap));
+        for (i, pixel) in pixels.iter().enumerate() {
+            let color = pixel.to_array();
+            self.allegro_core.put_pixel(
+                (i % image.width()) as i32,
+                (i / image.width()) as i32,
+                allegro::Color::from_rgba(color[0], color[1], color[2], color[3]),
+            );
         }
+        self.allegro_core.set_target_bitmap(Some(self.allegro_display.get_backbuffer()));
+        Some(bitmap)
     }
 }
 
+/// map allegro key codes to egui keys. Returns `None` for keys egui does not know
+fn translate_key(keycode: allegro::KeyCode) -> Option<egui::Key> {
+    use allegro::KeyCode;
+    let key = match keycode {
+        KeyCode::Down => egui::Key::ArrowDown,
+        KeyCode::Left => egui::Key::ArrowLeft,
+        KeyCode::Right => egui::Key::ArrowRight,
+        KeyCode::Up => egui::Key::ArrowUp,
+        KeyCode::Escape => egui::Key::Escape,
+        KeyCode::Tab => egui::Key::Tab,
+        KeyCode::Backspace => egui::Key::Backspace,
+        KeyCode::Enter | KeyCode::PadEnter => egui::Key::Enter,
+        KeyCode::Space => egui::Key::Space,
+        KeyCode::Insert => egui::Key::Insert,
+        KeyCode::Delete | KeyCode::PadDelete => egui::Key::Delete,
+        KeyCode::Home => egui::Key::Home,
+        KeyCode::End => egui::Key::End,
+        KeyCode::PgUp => egui::Key::PageUp,
+        KeyCode::PgDn => egui::Key::PageDown,
+        KeyCode::_0 | KeyCode::Pad0 => egui::Key::Num0,
+        KeyCode::_1 | KeyCode::Pad1 => egui::Key::Num1,
+        KeyCode::_2 | KeyCode::Pad2 => egui::Key::Num2,
+        KeyCode::_3 | KeyCode::Pad3 => egui::Key::Num3,
+        KeyCode::_4 | KeyCode::Pad4 => egui::Key::Num4,
+        KeyCode::_5 | KeyCode::Pad5 => egui::Key::Num5,
+        KeyCode::_6 | KeyCode::Pad6 => egui::Key::Num6,
+        KeyCode::_7 | KeyCode::Pad7 => egui::Key::Num7,
+        KeyCode::_8 | KeyCode::Pad8 => egui::Key::Num8,
+        KeyCode::_9 | KeyCode::Pad9 => egui::Key::Num9,
+        KeyCode::A => egui::Key::A,
+        KeyCode::B => egui::Key::B,
+        KeyCode::C => egui::Key::C,
+        KeyCode::D => egui::Key::D,
+        KeyCode::E => egui::Key::E,
+        KeyCode::F => egui::Key::F,
+        KeyCode::G => egui::Key::G,
+        KeyCode::H => egui::Key::H,
+        KeyCode::I => egui::Key::I,
+        KeyCode::J => egui::Key::J,
+        KeyCode::K => egui::Key::K,
+        KeyCode::L => egui::Key::L,
+        KeyCode::M => egui::Key::M,
+        KeyCode::N => egui::Key::N,
+        KeyCode::O => egui::Key::O,
+        KeyCode::P => egui::Key::P,
+        KeyCode::Q => egui::Key::Q,
+        KeyCode::R => egui::Key::R,
+        KeyCode::S => egui::Key::S,
+        KeyCode::T => egui::Key::T,
+        KeyCode::U => egui::Key::U,
+        KeyCode::V => egui::Key::V,
+        KeyCode::W => egui::Key::W,
+        KeyCode::X => egui::Key::X,
+        KeyCode::Y => egui::Key::Y,
+        KeyCode::Z => egui::Key::Z,
+        KeyCode::F1 => egui::Key::F1,
+        KeyCode::F2 => egui::Key::F2,
+        KeyCode::F3 => egui::Key::F3,
+        KeyCode::F4 => egui::Key::F4,
+        KeyCode::F5 => egui::Key::F5,
+        KeyCode::F6 => egui::Key::F6,
+        KeyCode::F7 => egui::Key::F7,
+        KeyCode::F8 => egui::Key::F8,
+        KeyCode::F9 => egui::Key::F9,
+        KeyCode::F10 => egui::Key::F10,
+        KeyCode::F11 => egui::Key::F11,
+        KeyCode::F12 => egui::Key::F12,
+        _ => return None,
+    };
+    Some(key)
+}
+
 struct VertexBuffer {
     data: Vec<allegro_primitives::Vertex>,
 }
@@ -1,5 +1,7 @@
 //! Allegro game engine initialization
+use super::autotile;
 use crate::glob::*;
+use crate::world::island::tile::{Biome, Building, Resource};
 use std::rc::Rc;
 
 const PATH_NAMES: [&str; TextureType::NumBitmaps as usize] = [
@@ -17,6 +19,7 @@ const PATH_NAMES: [&str; TextureType::NumBitmaps as usize] = [
 ];
 
 #[allow(unused)]
+#[derive(Clone, Copy)]
 pub enum TextureType {
     FocusedRed = 0,
     Tile,
@@ -32,6 +35,29 @@ pub enum TextureType {
     NumBitmaps,
 }
 
+impl TextureType {
+    /// texture that shows terrain of type `biome`
+    pub fn from_biome(biome: Biome) -> Self {
+        match biome {
+            Biome::Water => TextureType::Water,
+            Biome::Sand => TextureType::Sand,
+            Biome::Grass => TextureType::Grass,
+            Biome::Rock => TextureType::Rock,
+        }
+    }
+    pub fn from_resource(resource: Resource) -> Self {
+        match resource {
+            Resource::Tree => TextureType::Tree,
+        }
+    }
+    pub fn from_building(building: Building) -> Self {
+        match building {
+            Building::House => TextureType::House,
+            Building::Forester => TextureType::Forester,
+        }
+    }
+}
+
 pub struct Engine {
     pub core: Rc<allegro::Core>,
     pub event_queue: allegro::EventQueue,
@@ -39,8 +65,10 @@ pub struct Engine {
     pub primitives_addon: Rc<allegro_primitives::PrimitivesAddon>,
     _image_addon: allegro_image::ImageAddon,
     _font_addon: allegro_font::FontAddon,
-    _font: allegro_font::Font,
-    pub bitmaps: Vec<allegro::Bitmap>,
+    pub font: allegro_font::Font,
+    /// textures indexed by `TextureType`. Shared with the egui backend
+    pub bitmaps: Vec<Rc<allegro::Bitmap>>,
+    pub transitions: autotile::Transitions,
     timer: allegro::Timer,
 }
 
@@ -62,6 +90,8 @@ pub enum EngineError {
     FontAddon(String),
     #[error("Failed to load allegro bitmap: {0}")]
     LoadBitmap(String),
+    #[error("Failed to load terrain transitions: {0}")]
+    Transitions(autotile::AutotileError),
     #[error("Failed to create allegro font")]
     LoadFonts,
     #[error("Failed to create allegro timer")]
@@ -72,6 +102,31 @@ pub enum EngineError {
     Mouse,
 }
 
+/// Index into the textures of the texture file named `name` without extension
+pub fn texture_index(name: &str) -> Option<usize> {
+    PATH_NAMES
+        .iter()
+        .position(|path| std::path::Path::new(path).file_stem() == Some(std::ffi::OsStr::new(name)))
+}
+
+/// Load all textures, indexed by `TextureType`.
+///
+/// Without a display they are memory bitmaps
+pub fn load_bitmaps(core: &allegro::Core) -> Result<Vec<Rc<allegro::Bitmap>>, EngineError> {
+    let mut bitmaps: Vec<Rc<allegro::Bitmap>> = Vec::new();
+    for path in &PATH_NAMES {
+        match allegro::Bitmap::load(core, path) {
+            Ok(b) => bitmaps.push(Rc::new(b)),
+            Err(_) => return Err(
+                EngineError::LoadBitmap(
+                    format!("Failed to load bitmap {}", path)
+                )
+            ),
+        }
+    }
+    Ok(bitmaps)
+}
+
 impl Engine {
     pub fn new(fps: f32, screen_size: types::ScreenCoordinate) -> Result<Self, EngineError> {
         let core = match allegro::Core::init() {
@@ -111,18 +166,10 @@ impl Engine {
             Err(e) => return Err(EngineError::FontAddon(e)),
         };
 
-        // load bitmaps TODO
-        let mut bitmaps: Vec<allegro::Bitmap> = Vec::new();
-        for path in &PATH_NAMES {
-            match allegro::Bitmap::load(&core, path) {
-                Ok(b) => bitmaps.push(b),
-                Err(_) => return Err(
-                    EngineError::LoadBitmap(
-                        format!("Failed to load bitmap {}", path)
-                    )
-                ),
-            }
-        }
+        let bitmaps = load_bitmaps(&core)?;
+        let transitions = autotile::Transitions::load(&core, &bitmaps, autotile::TRANSITIONS_PATH)
+            .map_err(EngineError::Transitions)?;
+        core.set_target_bitmap(Some(display.get_backbuffer()));
 
         let font = match allegro_font::Font::new_builtin(&font_addon) {
             Ok(f) => f,
@@ -152,8 +199,9 @@ impl Engine {
             primitives_addon,
             _image_addon: image_addon,
             _font_addon: font_addon,
-            _font: font,
+            font,
             bitmaps,
+            transitions,
             timer,
         })
     }
@@ -0,0 +1,108 @@
+//! Write images of the world to disk
+//!
+//! Works with and without a display. Without one, all bitmaps live in RAM.
+use super::autotile::{self, Transitions};
+use super::engine;
+use super::ocean::Ocean;
+use super::map::{MapLayer, MapPalette, MapRenderer};
+use super::terrain;
+use crate::glob;
+use crate::glob::types::*;
+use crate::world::World;
+use allegro::BitmapLike;
+use std::rc::Rc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Failed to create allegro core: {0}")]
+    Core(String),
+    #[error("Failed to create allegro image addon: {0}")]
+    ImageAddon(String),
+    #[error("Failed to create allegro primitives addon: {0}")]
+    PrimitivesAddon(String),
+    #[error("Failed to load textures: {0}")]
+    Engine(engine::EngineError),
+    #[error("Failed to load terrain transitions: {0}")]
+    Transitions(autotile::AutotileError),
+    #[error("Failed to create bitmap of size {0} x {1}")]
+    CreateBitmap(i32, i32),
+    #[error("Failed to save {0}")]
+    Save(String),
+}
+
+/// Zoom of the world image written by `export_headless`
+const HEADLESS_SCALE: f32 = 0.25;
+
+/// Save `bitmap` to `path`. The file type is determined by the extension
+pub fn save_bitmap<T: BitmapLike>(bitmap: &T, path: &str) -> Result<(), ExportError> {
+    let c_path = std::ffi::CString::new(path).map_err(|_| ExportError::Save(path.to_string()))?;
+    let saved = unsafe { allegro_sys::al_save_bitmap(c_path.as_ptr(), bitmap.get_allegro_bitmap()) };
+    if saved == 0 {
+        return Err(ExportError::Save(path.to_string()));
+    }
+    log::info!("Saved {}", path);
+    Ok(())
+}
+
+/// Render `area` of the world in isometric view at zoom `scale`, turned by `rotation`.
+///
+/// The bitmap is created with the current new bitmap flags and stays the target bitmap.
+#[allow(clippy::too_many_arguments)]
+pub fn render_world(
+    allegro_core: &allegro::Core,
+    primitives_addon: &allegro_primitives::PrimitivesAddon,
+    bitmaps: &[Rc<allegro::Bitmap>],
+    transitions: &Transitions,
+    world: &World,
+    area: WorldRect,
+    scale: f32,
+    rotation: Rotation,
+) -> Result<allegro::Bitmap, ExportError> {
+    // the area is a diamond on screen, raised tiles stick out of it by up to one texture
+    let w2s = gen_w2s_matrix(scale, area.center(), ScreenCoordinate::new(0.0, 0.0), rotation);
+    let screen_bounds = ScreenRect::from_points(rect_corners(&area).map(|corner| w2s.transform_point(corner)))
+        .inflate(glob::TILE_TEXTURE_SIZE.x * scale, glob::TILE_TEXTURE_SIZE.y * scale);
+    let width = screen_bounds.width().ceil() as i32;
+    let height = screen_bounds.height().ceil() as i32;
+    let image = allegro::Bitmap::new(allegro_core, width, height)
+        .map_err(|_| ExportError::CreateBitmap(width, height))?;
+
+    let screen_area = ScreenRect::new(ScreenCoordinate::new(0.0, 0.0), ScreenVector::new(width as f32, height as f32).to_size());
+    let view = terrain::View {
+        w2s: gen_w2s_matrix(scale, area.center(), screen_area.center(), rotation),
+        world_area: area,
+        screen_area,
+        scale,
+        rotation,
+    };
+    allegro_core.set_target_bitmap(Some(&image));
+    Ocean::default().draw(
+        allegro_core,
+        primitives_addon,
+        &bitmaps[engine::TextureType::Water as usize],
+        world,
+        &view,
+        false,
+    );
+    terrain::draw(allegro_core, primitives_addon, bitmaps, transitions, world, &view, terrain::Detail::Sprites);
+    Ok(image)
+}
+
+/// Write the map and an image of `area`, or the entire `world` if `None`, to `dir` without opening a window
+pub fn export_headless(world: &World, dir: &str, area: Option<WorldRect>) -> Result<(), ExportError> {
+    let allegro_core = allegro::Core::init().map_err(ExportError::Core)?;
+    let _image_addon = allegro_image::ImageAddon::init(&allegro_core).map_err(ExportError::ImageAddon)?;
+    let primitives_addon = allegro_primitives::PrimitivesAddon::init(&allegro_core).map_err(ExportError::PrimitivesAddon)?;
+    let bitmaps = engine::load_bitmaps(&allegro_core).map_err(ExportError::Engine)?;
+    let transitions = Transitions::load(&allegro_core, &bitmaps, autotile::TRANSITIONS_PATH)
+        .map_err(ExportError::Transitions)?;
+
+    let mut map_renderer = MapRenderer::new(&allegro_core);
+    map_renderer.render(world, MapLayer::Height, MapPalette::Default, &allegro_core);
+    save_bitmap(map_renderer.map(), &format!("{}/map.png", dir))?;
+
+    allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
+    let image = render_world(&allegro_core, &primitives_addon, &bitmaps, &transitions, world, area.unwrap_or(world.clipping_rect), HEADLESS_SCALE, Rotation::default())?;
+    save_bitmap(&image, &format!("{}/world.png", dir))
+}
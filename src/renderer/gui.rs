@@ -1,9 +1,123 @@
+use super::engine::TextureType;
+use super::map::{legend, MapLayer, MapPalette};
+use super::overlay::DebugOverlays;
+use super::stage_viewer::StageViewer;
 use crate::glob::types::*;
+use crate::grid::GridIndex;
+use crate::selection::{tile_center, Brush, BrushShape, Selection, SelectionShape};
+use crate::editor::EditTool;
+use crate::user_cmds::{HistoryCmd, Tool};
+use crate::world::island::tile::{Biome, Building, Resource};
+use crate::world::island::heightmap::Pipeline;
+use crate::world::island::{IslandParams, MAX_INTERPOLATION_SCALE, MAX_RANDMAP_EXP, MIN_INTERPOLATION_SCALE, MIN_RANDMAP_EXP};
+use crate::world::{chunk_index, ChunkIndex, World};
 #[derive(PartialEq, Eq)]
 pub enum SidePanelTab {
     Main,
     Settings,
     Debug,
+    Inspector,
+    Editor,
+}
+
+/// Everything known about the tile at a certain world position
+pub struct TileInfo {
+    /// world position of the tile's upper left corner
+    pub pos: WorldCoordinate,
+    pub chunk: ChunkIndex,
+    pub chunk_generated: bool,
+    /// index of the owning island in `World::islands`
+    pub island: Option<usize>,
+    /// tile index inside the owning island
+    pub tile_index: Option<GridIndex>,
+    pub height: f32,
+    pub biome: Biome,
+    pub resource: Option<Resource>,
+    pub building: Option<Building>,
+}
+
+impl TileInfo {
+    pub fn new(world: &World, pos: WorldCoordinate) -> Self {
+        let pos = pos.floor();
+        let chunk = chunk_index(pos);
+        let mut info = TileInfo {
+            pos,
+            chunk,
+            chunk_generated: world.chunks.contains_key(&chunk),
+            island: None,
+            tile_index: None,
+            height: 0.0,
+            biome: Biome::Water,
+            resource: None,
+            building: None,
+        };
+        if let Some((island, ind)) = world.tile_at(pos) {
+            let tile = &world.islands[island].tiles[ind];
+            info.island = Some(island);
+            info.tile_index = Some(ind);
+            info.height = tile.height();
+            info.biome = tile.biome();
+            info.resource = tile.resource();
+            info.building = tile.building();
+        }
+        info
+    }
+
+    fn show(&self, ui: &mut egui::Ui, textures: &[egui::TextureId]) {
+        if !textures.is_empty() {
+            let mut sprites = vec![TextureType::from_biome(self.biome)];
+            sprites.extend(self.resource.map(TextureType::from_resource));
+            sprites.extend(self.building.map(TextureType::from_building));
+            ui.horizontal(|ui| {
+                for sprite in sprites {
+                    ui.image(textures[sprite as usize], egui::Vec2::new(SPRITE_SIZE, SPRITE_SIZE));
+                }
+            });
+        }
+        egui::Grid::new("TileInfo").num_columns(2).show(ui, |ui| {
+            ui.label("World");
+            ui.label(format!("{} {}", self.pos.x, self.pos.y));
+            ui.end_row();
+            ui.label("Chunk");
+            ui.label(format!(
+                "{} {}{}",
+                self.chunk.x,
+                self.chunk.y,
+                if self.chunk_generated { "" } else { " (pending)" }
+            ));
+            ui.end_row();
+            ui.label("Island");
+            match self.island {
+                Some(i) => ui.label(format!("{}", i)),
+                None => ui.label("-"),
+            };
+            ui.end_row();
+            ui.label("Tile");
+            match self.tile_index {
+                Some(ind) => ui.label(format!("{} {}", ind.x, ind.y)),
+                None => ui.label("-"),
+            };
+            ui.end_row();
+            ui.label("Height");
+            ui.label(format!("{:.3}", self.height));
+            ui.end_row();
+            ui.label("Biome");
+            ui.label(format!("{:?}", self.biome));
+            ui.end_row();
+            ui.label("Resource");
+            match self.resource {
+                Some(r) => ui.label(format!("{:?}", r)),
+                None => ui.label("-"),
+            };
+            ui.end_row();
+            ui.label("Building");
+            match self.building {
+                Some(b) => ui.label(format!("{:?}", b)),
+                None => ui.label("-"),
+            };
+            ui.end_row();
+        });
+    }
 }
 pub struct GuiInfo {
     pub drawn_tiles: usize,
@@ -13,6 +127,280 @@ pub struct GuiInfo {
     pub rendered_rect: ScreenRect,
     pub mouse_pos: WorldCoordinate,
     pub show_map: bool,
+    pub show_stages: bool,
+    /// tile under the mouse cursor
+    pub hovered_tile: Option<TileInfo>,
+    /// tile selected by left click. Reset to `None` to unpin
+    pub pinned_tile: Option<TileInfo>,
+    /// last selection dragged out or clicked
+    pub selection: Option<SelectionInfo>,
+    pub tool: Tool,
+    pub selection_shape: SelectionShape,
+    pub brush: Brush,
+    /// see `Editor::apply`
+    pub brush_strength: f32,
+    /// undo and redo requests. Taken by the renderer
+    pub history: Vec<HistoryCmd>,
+    /// write the world to a file. Reset by the renderer
+    pub save_world: bool,
+    /// island of the pinned tile
+    pub island: Option<IslandInfo>,
+    /// island index and the parameters to regenerate it from. Taken by the renderer
+    pub regenerate_island: Option<(usize, IslandParams)>,
+    pub stages: StageViewer,
+    pub overlays: DebugOverlays,
+    /// game sprites registered with the egui backend, indexed by `TextureType`
+    pub textures: Vec<egui::TextureId>,
+    pub map: MapInfo,
+    /// write the map to a PNG file. Reset by the renderer
+    pub export_map: bool,
+    /// write `export_area` of the world at full resolution to a PNG file. Reset by the renderer
+    pub export_view: bool,
+    /// world area written by `export_view`. Follows the view unless `custom_export_area`
+    pub export_area: WorldRect,
+    pub custom_export_area: bool,
+    /// result of the last export or save
+    pub export_status: String,
+}
+
+/// Summary of the last finished selection
+pub struct SelectionInfo {
+    pub tiles: usize,
+    /// tiles above water
+    pub land: usize,
+    pub buildings: usize,
+}
+
+impl SelectionInfo {
+    pub fn new(world: &World, selection: &Selection) -> Self {
+        let mut info = SelectionInfo {
+            tiles: selection.len(),
+            land: 0,
+            buildings: 0,
+        };
+        for tile in selection.tiles().filter_map(|pos| world.tile(tile_center(pos))) {
+            info.land += (tile.biome() != Biome::Water) as usize;
+            info.buildings += tile.building().is_some() as usize;
+        }
+        info
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("SelectionInfo").num_columns(2).show(ui, |ui| {
+            ui.label("Tiles");
+            ui.label(format!("{}", self.tiles));
+            ui.end_row();
+            ui.label("Land");
+            ui.label(format!("{}", self.land));
+            ui.end_row();
+            ui.label("Buildings");
+            ui.label(format!("{}", self.buildings));
+            ui.end_row();
+        });
+    }
+}
+
+/// Generation parameters of an island, edited before regenerating it
+pub struct IslandInfo {
+    /// index in `World::islands`
+    pub index: usize,
+    pub params: IslandParams,
+    /// recipe of the world, the standard one if `None`
+    pub recipe: Option<Pipeline>,
+}
+
+impl IslandInfo {
+    fn pipeline(&self) -> Pipeline {
+        self.recipe.clone().unwrap_or_else(|| Pipeline::standard(&self.params))
+    }
+
+    /// Show the parameters for editing. Returns true if the island should be regenerated
+    fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut regenerate = false;
+        egui::Grid::new("IslandInfo").num_columns(2).show(ui, |ui| {
+            ui.label("Island");
+            ui.label(format!("{}", self.index));
+            ui.end_row();
+            ui.label("Seed");
+            ui.add(egui::DragValue::new(&mut self.params.seed));
+            ui.end_row();
+        });
+        if self.recipe.is_some() {
+            ui.label("Size and scale are set by the recipe");
+        } else {
+            ui.add(egui::Slider::new(&mut self.params.randmap_exp, MIN_RANDMAP_EXP..=MAX_RANDMAP_EXP - 1).text("Size"));
+            ui.add(egui::Slider::new(&mut self.params.interpolation_scale, MIN_INTERPOLATION_SCALE..=MAX_INTERPOLATION_SCALE - 1).text("Scale"));
+        }
+        ui.horizontal(|ui| {
+            regenerate = ui.button("Regenerate").clicked();
+            if ui.button("New seed").clicked() {
+                self.params = self.params.reseeded();
+                regenerate = true;
+            }
+        });
+        regenerate
+    }
+}
+
+/// State of the map window
+pub struct MapInfo {
+    pub texture: Option<egui::TextureId>,
+    /// world area covered by the map texture
+    pub area: WorldRect,
+    /// corners of the area visible on screen
+    pub viewport: [WorldCoordinate; 4],
+    /// magnification, 1.0 shows the entire map
+    pub zoom: f32,
+    /// world position shown in the middle of the map window
+    pub center: WorldCoordinate,
+    /// world position the user clicked on. Taken by the renderer
+    pub clicked: Option<WorldCoordinate>,
+    pub layer: MapLayer,
+    pub palette: MapPalette,
+}
+
+impl Default for MapInfo {
+    fn default() -> Self {
+        MapInfo {
+            texture: None,
+            area: WorldRect::default(),
+            viewport: [WorldCoordinate::new(0.0, 0.0); 4],
+            zoom: 1.0,
+            center: WorldCoordinate::new(0.0, 0.0),
+            clicked: None,
+            layer: MapLayer::Height,
+            palette: MapPalette::Default,
+        }
+    }
+}
+
+const MAP_WIDTH: f32 = 300.0;
+const MAX_MAP_ZOOM: f32 = 16.0;
+/// zoom factor per scrolled point
+const MAP_ZOOM_SPEED: f32 = 0.004;
+
+impl MapInfo {
+    fn show(&mut self, ui: &mut egui::Ui) {
+        let texture = match self.texture {
+            Some(t) => t,
+            None => return,
+        };
+        if self.area.is_empty() {
+            ui.label("Nothing generated yet");
+            return;
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("MapLayer")
+                .selected_text(format!("{:?}", self.layer))
+                .show_ui(ui, |ui| {
+                    for layer in MapLayer::ALL {
+                        ui.selectable_value(&mut self.layer, layer, format!("{:?}", layer));
+                    }
+                });
+            let mut color_blind = self.palette == MapPalette::ColorBlind;
+            ui.checkbox(&mut color_blind, "Colour-blind palette");
+            self.palette = if color_blind { MapPalette::ColorBlind } else { MapPalette::Default };
+        });
+        ui.add(egui::Slider::new(&mut self.zoom, 1.0..=MAX_MAP_ZOOM).logarithmic(true).text("Zoom"));
+
+        // keep the visible part of the map inside the texture
+        let visible_size = self.area.size / self.zoom;
+        self.center = self.center.clamp(
+            self.area.min() + visible_size.to_vector() / 2.0,
+            self.area.max() - visible_size.to_vector() / 2.0,
+        );
+        let visible = WorldRect::new(self.center - visible_size.to_vector() / 2.0, visible_size);
+        let uv = egui::Rect::from_min_max(
+            egui::Pos2::new(
+                (visible.min_x() - self.area.min_x()) / self.area.width(),
+                (visible.min_y() - self.area.min_y()) / self.area.height(),
+            ),
+            egui::Pos2::new(
+                (visible.max_x() - self.area.min_x()) / self.area.width(),
+                (visible.max_y() - self.area.min_y()) / self.area.height(),
+            ),
+        );
+        let display_size = egui::Vec2::new(MAP_WIDTH, MAP_WIDTH * self.area.height() / self.area.width());
+        let response = ui.add(
+            egui::Image::new(texture, display_size)
+                .uv(uv)
+                .sense(egui::Sense::click_and_drag()),
+        );
+        let rect = response.rect;
+        let to_screen = |pos: WorldCoordinate| {
+            egui::Pos2::new(
+                rect.min.x + (pos.x - visible.min_x()) / visible.width() * rect.width(),
+                rect.min.y + (pos.y - visible.min_y()) / visible.height() * rect.height(),
+            )
+        };
+        let to_world = |pos: egui::Pos2| {
+            WorldCoordinate::new(
+                visible.min_x() + (pos.x - rect.min.x) / rect.width() * visible.width(),
+                visible.min_y() + (pos.y - rect.min.y) / rect.height() * visible.height(),
+            )
+        };
+
+        if response.hovered() {
+            let scroll = ui.input().scroll_delta.y;
+            self.zoom = (self.zoom * (1.0 + scroll * MAP_ZOOM_SPEED)).clamp(1.0, MAX_MAP_ZOOM);
+        }
+        if response.dragged() {
+            let delta = response.drag_delta();
+            self.center -= WorldVector::new(
+                delta.x / rect.width() * visible.width(),
+                delta.y / rect.height() * visible.height(),
+            );
+        }
+        if response.clicked() {
+            self.clicked = response.interact_pointer_pos().map(to_world);
+        }
+
+        ui.painter_at(rect).add(egui::Shape::closed_line(
+            self.viewport.iter().map(|corner| to_screen(*corner)).collect(),
+            egui::Stroke::new(1.0, egui::Color32::RED),
+        ));
+
+        ui.collapsing("Legend", |ui| {
+            egui::Grid::new("MapLegend").num_columns(2).show(ui, |ui| {
+                for (label, color) in legend(self.layer, self.palette) {
+                    let (swatch, _) = ui.allocate_exact_size(egui::Vec2::splat(12.0), egui::Sense::hover());
+                    ui.painter().rect_filled(swatch, 0.0, egui::Color32::from_rgb(color[0], color[1], color[2]));
+                    ui.label(label);
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+/// edge length of game sprites shown in the GUI
+const SPRITE_SIZE: f32 = 64.0;
+/// largest brush offered, in tiles from the centre
+const MAX_BRUSH_RADIUS: usize = 8;
+
+/// drag shape and brush, shared by all tools
+fn selection_controls(ui: &mut egui::Ui, args: &mut GuiInfo) {
+    ui.strong("Drag");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut args.selection_shape, SelectionShape::Rectangle, "Rectangle");
+        ui.selectable_value(&mut args.selection_shape, SelectionShape::Lasso, "Lasso");
+    });
+    ui.strong("Brush");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut args.brush.shape, BrushShape::Square, "Square");
+        ui.selectable_value(&mut args.brush.shape, BrushShape::Circle, "Circle");
+    });
+    ui.add(egui::Slider::new(&mut args.brush.radius, 0..=MAX_BRUSH_RADIUS).text("Radius"));
+}
+
+/// Fields for the corners of `area`, keeping the maximum at or above the minimum
+fn export_area_input(ui: &mut egui::Ui, area: &mut WorldRect) {
+    let (mut min, mut max) = (area.min(), area.max());
+    ui.add(egui::DragValue::new(&mut min.x).prefix("x "));
+    ui.add(egui::DragValue::new(&mut min.y).prefix("y "));
+    ui.label("to");
+    ui.add(egui::DragValue::new(&mut max.x).prefix("x ").clamp_range(min.x..=f32::MAX));
+    ui.add(egui::DragValue::new(&mut max.y).prefix("y ").clamp_range(min.y..=f32::MAX));
+    *area = WorldRect::from_points([min, max.max(min)]);
 }
 
 pub fn draw_gui(ctx: &egui::Context, args: &mut GuiInfo) {
@@ -33,11 +421,68 @@ pub fn draw_gui(ctx: &egui::Context, args: &mut GuiInfo) {
                     SidePanelTab::Settings,
                     "Settings",
                 );
+                ui.selectable_value(
+                    &mut args.active_side_panel_tab,
+                    SidePanelTab::Inspector,
+                    "Inspector",
+                );
+                ui.selectable_value(&mut args.active_side_panel_tab, SidePanelTab::Editor, "Editor");
             });
             ui.separator();
             match args.active_side_panel_tab {
                 SidePanelTab::Main => {
                     ui.label("New Game");
+                    ui.separator();
+                    ui.strong("Tool");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut args.tool, Tool::Inspect, "Inspect");
+                        ui.selectable_value(&mut args.tool, Tool::Build(Building::House), "House");
+                        ui.selectable_value(&mut args.tool, Tool::Build(Building::Forester), "Forester");
+                    });
+                    selection_controls(ui, args);
+                }
+                SidePanelTab::Editor => {
+                    ui.strong("Height");
+                    ui.horizontal_wrapped(|ui| {
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::Raise), "Raise");
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::Lower), "Lower");
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::Flatten), "Flatten");
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::Smooth), "Smooth");
+                    });
+                    ui.strong("Paint");
+                    ui.horizontal_wrapped(|ui| {
+                        for biome in [Biome::Water, Biome::Sand, Biome::Grass, Biome::Rock] {
+                            ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::PaintBiome(biome)), format!("{:?}", biome));
+                        }
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::PaintResource(Some(Resource::Tree))), "Tree");
+                        ui.selectable_value(&mut args.tool, Tool::Edit(EditTool::PaintResource(None)), "Clear");
+                    });
+                    ui.add(egui::Slider::new(&mut args.brush_strength, 0.1..=4.0).text("Strength"));
+                    selection_controls(ui, args);
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("Undo").on_hover_text("Ctrl+Z").clicked() {
+                            args.history.push(HistoryCmd::Undo);
+                        }
+                        if ui.button("Redo").on_hover_text("Ctrl+Y").clicked() {
+                            args.history.push(HistoryCmd::Redo);
+                        }
+                        if ui.button("Save world").clicked() {
+                            args.save_world = true;
+                        }
+                    });
+                    ui.separator();
+                    ui.strong("Island");
+                    match &mut args.island {
+                        Some(island) => {
+                            if island.show(ui) {
+                                args.regenerate_island = Some((island.index, island.params));
+                            }
+                        }
+                        None => {
+                            ui.label("Click an island to edit it");
+                        }
+                    }
                 }
                 SidePanelTab::Settings => {
                     ui.label("Settings");
@@ -52,13 +497,80 @@ pub fn draw_gui(ctx: &egui::Context, args: &mut GuiInfo) {
                     args.rendered_rect = ScreenRect::new(ScreenCoordinate::new(0.0, 0.0), ScreenVector::new(render_width, render_height).to_size());
                     ui.label(format!("Mouse X: {}", args.mouse_pos.x));
                     ui.label(format!("Mouse Y: {}", args.mouse_pos.y));
+                    ui.collapsing("Overlays", |ui| {
+                        ui.checkbox(&mut args.overlays.chunk_bounds, "Chunk bounds");
+                        ui.checkbox(&mut args.overlays.chunk_state, "Generated/pending chunks");
+                        ui.checkbox(&mut args.overlays.island_rects, "Island clipping rects");
+                        ui.checkbox(&mut args.overlays.world_rect, "World clipping rect");
+                        ui.checkbox(&mut args.overlays.render_areas, "Rendered area/screen on world");
+                    });
+                }
+                SidePanelTab::Inspector => {
+                    let mut unpin = false;
+                    if let Some(tile) = &args.pinned_tile {
+                        ui.horizontal(|ui| {
+                            ui.strong("Pinned");
+                            unpin = ui.button("Unpin").clicked();
+                        });
+                        tile.show(ui, &args.textures);
+                        ui.separator();
+                    }
+                    if unpin {
+                        args.pinned_tile = None;
+                    }
+                    if let Some(selection) = &args.selection {
+                        ui.strong("Selection");
+                        selection.show(ui);
+                        ui.separator();
+                    }
+                    ui.strong("Hovered");
+                    match &args.hovered_tile {
+                        Some(tile) => tile.show(ui, &args.textures),
+                        None => {
+                            ui.label("-");
+                        }
+                    }
                 }
             }
         });
+    egui::Window::new("Map")
+        .open(&mut args.show_map)
+        .show(ctx, |ui| args.map.show(ui));
+    egui::Window::new("Generation stages")
+        .open(&mut args.show_stages)
+        .show(ctx, |ui| match &mut args.island {
+            Some(island) => {
+                if island.show(ui) {
+                    args.regenerate_island = Some((island.index, island.params));
+                }
+                ui.separator();
+                args.stages.show(ui, island.params, island.pipeline());
+            }
+            None => {
+                ui.label("Click an island to see how it was generated");
+            }
+        });
     egui::TopBottomPanel::bottom("Toolbar").show(ctx, |ui| {
-        if ui.button("Map").clicked() {
-            args.show_map = !args.show_map;
-        }
+        ui.horizontal(|ui| {
+            if ui.button("Map").clicked() {
+                args.show_map = !args.show_map;
+            }
+            if ui.button("Stages").on_hover_text("Island generation stages").clicked() {
+                args.show_stages = !args.show_stages;
+            }
+            if ui.button("Export map").clicked() {
+                args.export_map = true;
+            }
+            if ui.button("Export view").on_hover_text("F12 exports map and view").clicked() {
+                args.export_view = true;
+            }
+            ui.checkbox(&mut args.custom_export_area, "Area")
+                .on_hover_text("Export a chosen world rectangle instead of the view");
+            if args.custom_export_area {
+                export_area_input(ui, &mut args.export_area);
+            }
+            ui.label(&args.export_status);
+        });
     });
 }
 
@@ -72,6 +584,27 @@ impl Default for GuiInfo {
             rendered_rect: ScreenRect::from_size(ScreenVector::new(1.0, 1.0).to_size()),
             mouse_pos: WorldCoordinate::new(0.0, 0.0),
             show_map: false,
+            show_stages: false,
+            hovered_tile: None,
+            pinned_tile: None,
+            selection: None,
+            tool: Tool::default(),
+            selection_shape: SelectionShape::default(),
+            brush: Brush::default(),
+            brush_strength: 1.0,
+            history: Vec::new(),
+            save_world: false,
+            island: None,
+            regenerate_island: None,
+            stages: StageViewer::default(),
+            overlays: DebugOverlays::default(),
+            textures: Vec::new(),
+            map: MapInfo::default(),
+            export_map: false,
+            export_view: false,
+            export_area: WorldRect::default(),
+            custom_export_area: false,
+            export_status: String::new(),
         }
     }
 }
@@ -0,0 +1,43 @@
+//! Level of detail of the world depending on the zoom
+//!
+//! Close up, tiles are sprites. Further out they become coloured diamonds and finally the map is
+//! shown in isometric view. Just above each threshold the finer tier fades in over the coarser one.
+use super::terrain::Detail;
+
+/// Zoom below which tiles are coloured diamonds
+pub const FLAT_SCALE: f32 = 0.5;
+/// Zoom below which the map is shown
+pub const MAP_SCALE: f32 = 0.3;
+/// Zoom range above each threshold in which both tiers are drawn
+pub const BLEND_RANGE: f32 = 0.05;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lod {
+    Terrain(Detail),
+    Map,
+}
+
+/// Tiers to draw at zoom `scale` with their opacity, coarsest first
+pub fn tiers(scale: f32) -> Vec<(Lod, f32)> {
+    let fade_in = |threshold: f32| (scale - threshold) / BLEND_RANGE;
+    if scale >= FLAT_SCALE + BLEND_RANGE {
+        vec![(Lod::Terrain(Detail::Sprites), 1.0)]
+    }
+    else if scale >= FLAT_SCALE {
+        vec![(Lod::Terrain(Detail::Flat), 1.0), (Lod::Terrain(Detail::Sprites), fade_in(FLAT_SCALE))]
+    }
+    else if scale >= MAP_SCALE + BLEND_RANGE {
+        vec![(Lod::Terrain(Detail::Flat), 1.0)]
+    }
+    else if scale >= MAP_SCALE {
+        vec![(Lod::Map, 1.0), (Lod::Terrain(Detail::Flat), fade_in(MAP_SCALE))]
+    }
+    else {
+        vec![(Lod::Map, 1.0)]
+    }
+}
+
+/// True if the map is drawn at zoom `scale`
+pub fn shows_map(scale: f32) -> bool {
+    scale < MAP_SCALE + BLEND_RANGE
+}
@@ -1,115 +1,356 @@
 use crate::glob::types::*;
-use crate::world::{World, CHUNK_SIZE};
+use crate::grid::GridRect;
+use crate::world::island::Island;
+use crate::world::{ChunkIndex, World, CHUNK_SIZE};
+use std::collections::HashMap;
+use std::rc::Rc;
 
+/// Information shown on the map
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MapLayer {
+    /// terrain height with hillshading
+    Height,
+    Biome,
+    Resources,
+    /// island each tile belongs to
+    Ownership,
+    /// generated chunks
+    Chunks,
+}
+
+impl MapLayer {
+    pub const ALL: [MapLayer; 5] = [
+        MapLayer::Height,
+        MapLayer::Biome,
+        MapLayer::Resources,
+        MapLayer::Ownership,
+        MapLayer::Chunks,
+    ];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MapPalette {
+    Default,
+    /// colors that can be told apart with common forms of color blindness (Okabe-Ito)
+    ColorBlind,
+}
+
+pub type Rgb = [u8; 3];
+
+/// Colors used by all layers
+struct Colors {
+    /// area that was not generated yet
+    unexplored: Rgb,
+    /// generated area without islands
+    ocean: Rgb,
+    /// height ramp at heights 0, 1, 2 and 3
+    height: [Rgb; 4],
+    /// indexed by `Biome`
+    biomes: [Rgb; 4],
+    land: Rgb,
+    tree: Rgb,
+    /// islands cycle through these colors
+    islands: [Rgb; 6],
+    /// neighbouring chunks alternate between these colors
+    chunks: [Rgb; 2],
+}
+
+const DEFAULT_COLORS: Colors = Colors {
+    unexplored: [255, 255, 255],
+    ocean: [20, 40, 120],
+    height: [[40, 110, 200], [220, 210, 140], [60, 150, 60], [240, 240, 240]],
+    biomes: [[40, 110, 200], [255, 255, 0], [255, 0, 255], [128, 128, 128]],
+    land: [200, 200, 200],
+    tree: [0, 120, 0],
+    islands: [[230, 25, 75], [60, 180, 75], [255, 225, 25], [245, 130, 48], [145, 30, 180], [70, 240, 240]],
+    chunks: [[0, 255, 0], [0, 180, 0]],
+};
+
+const COLOR_BLIND_COLORS: Colors = Colors {
+    unexplored: [255, 255, 255],
+    ocean: [0, 0, 0],
+    height: [[68, 1, 84], [59, 82, 139], [33, 145, 140], [253, 231, 37]],
+    biomes: [[0, 114, 178], [240, 228, 66], [0, 158, 115], [204, 121, 167]],
+    land: [153, 153, 153],
+    tree: [230, 159, 0],
+    islands: [[230, 159, 0], [86, 180, 233], [0, 158, 115], [240, 228, 66], [0, 114, 178], [213, 94, 0]],
+    chunks: [[86, 180, 233], [0, 114, 178]],
+};
+
+impl MapPalette {
+    fn colors(&self) -> &'static Colors {
+        match self {
+            MapPalette::Default => &DEFAULT_COLORS,
+            MapPalette::ColorBlind => &COLOR_BLIND_COLORS,
+        }
+    }
+}
+
+/// Explanation of the colors of `layer`
+pub fn legend(layer: MapLayer, palette: MapPalette) -> Vec<(String, Rgb)> {
+    let colors = palette.colors();
+    let mut ret: Vec<(String, Rgb)> = match layer {
+        MapLayer::Height => ["Sea level", "Low", "Medium", "High"]
+            .iter()
+            .zip(colors.height)
+            .map(|(label, color)| (label.to_string(), color))
+            .collect(),
+        MapLayer::Biome => ["Water", "Sand", "Grass", "Rock"]
+            .iter()
+            .zip(colors.biomes)
+            .map(|(label, color)| (label.to_string(), color))
+            .collect(),
+        MapLayer::Resources => vec![
+            ("Tree".to_string(), colors.tree),
+            ("No resource".to_string(), colors.land),
+        ],
+        MapLayer::Ownership => colors
+            .islands
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                (format!("Island {}, {}, {}, ...", i, i + colors.islands.len(), i + 2 * colors.islands.len()), *color)
+            })
+            .collect(),
+        MapLayer::Chunks => vec![
+            ("Generated".to_string(), colors.chunks[0]),
+            ("Generated (neighbour)".to_string(), colors.chunks[1]),
+        ],
+    };
+    if layer != MapLayer::Chunks {
+        ret.push(("Ocean".to_string(), colors.ocean));
+    }
+    ret.push(("Not generated".to_string(), colors.unexplored));
+    ret
+}
+
+/// brightness change per height difference to the north-western neighbour
+const HILLSHADE: f32 = 4.0;
+
+/// Renders the world into a bitmap with one pixel per tile.
+///
+/// Only chunks that were added or changed since the last update are redrawn.
 pub struct MapRenderer {
+    /// map in RAM that is updated chunk by chunk
     map: allegro::Bitmap,
-    map_size: ScreenCoordinate
+    /// video copy of `map` that is shown in the GUI
+    texture: Rc<allegro::Bitmap>,
+    /// world area covered by the map
+    area: WorldRect,
+    /// revision of every chunk at the time it was drawn
+    rendered_chunks: HashMap<ChunkIndex, u64>,
+    /// true if `map` changed since `texture` was uploaded
+    upload_pending: bool,
+    layer: MapLayer,
+    palette: MapPalette,
 }
 
 impl MapRenderer {
     pub fn new(allegro_core: &allegro::Core) -> Self {
+        allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
         let map = allegro::Bitmap::new(allegro_core, 1, 1).unwrap();
-        let map_size = ScreenCoordinate::new(1.0, 1.0);
+        // placeholder until the first update
+        let texture = Rc::new(map.clone());
+        allegro_core.set_new_bitmap_flags(allegro::VIDEO_BITMAP);
         MapRenderer {
             map,
-            map_size,
+            texture,
+            area: WorldRect::default(),
+            rendered_chunks: HashMap::new(),
+            upload_pending: false,
+            layer: MapLayer::Height,
+            palette: MapPalette::Default,
         }
     }
+
+    /// Bitmap to draw. Replaced by `update`, so do not hold on to it
+    pub fn texture(&self) -> Rc<allegro::Bitmap> {
+        self.texture.clone()
+    }
+
+    /// World area covered by `texture`
+    pub fn area(&self) -> WorldRect {
+        self.area
+    }
+
+    /// Map in RAM as drawn by the last `render`
+    pub fn map(&self) -> &allegro::Bitmap {
+        &self.map
+    }
+
+    /// Draw new and changed chunks and upload them, along with changes of earlier `render` calls.
+    /// Returns true if `texture` was replaced
     pub fn update(
         &mut self,
         world: &World,
+        layer: MapLayer,
+        palette: MapPalette,
         allegro_core: &allegro::Core,
-        allegro_display: &allegro::Display) {
-        // create texture in RAM
+        allegro_display: &allegro::Display) -> bool {
+        self.render(world, layer, palette, allegro_core);
+        allegro_core.set_target_bitmap(Some(allegro_display.get_backbuffer()));
+        if !self.upload_pending {
+            return false;
+        }
+        self.upload_pending = false;
+        // no linear filter so tiles stay sharp when zooming in
+        allegro_core.set_new_bitmap_flags(allegro::VIDEO_BITMAP);
+        match allegro_display.convert_bitmap(&self.map) {
+            Ok(texture) => self.texture = Rc::new(texture),
+            Err(_) => log::error!("Cannot convert map texture"),
+        }
+        true
+    }
+
+    /// Draw new and changed chunks to the map in RAM without uploading it, which works without a display.
+    /// The next `update` uploads the changes. Returns true if anything changed. The map stays the target bitmap
+    pub fn render(
+        &mut self,
+        world: &World,
+        layer: MapLayer,
+        palette: MapPalette,
+        allegro_core: &allegro::Core) -> bool {
+        if world.clipping_rect.is_empty() {
+            return false;
+        }
+        if layer != self.layer || palette != self.palette {
+            // redraw everything
+            self.layer = layer;
+            self.palette = palette;
+            self.area = WorldRect::default();
+        }
+        let mut changed = false;
+        if world.clipping_rect != self.area {
+            self.resize(world.clipping_rect, allegro_core);
+            changed = true;
+        }
+        allegro_core.set_target_bitmap(Some(&self.map));
+        for (ind, chunk) in &world.chunks {
+            if self.rendered_chunks.get(ind) == Some(&chunk.revision) {
+                continue;
+            }
+            self.draw_chunk(*ind, world, allegro_core);
+            self.rendered_chunks.insert(*ind, chunk.revision);
+            changed = true;
+        }
+        self.upload_pending |= changed;
+        changed
+    }
+
+    /// grow the map to `area`, keeping everything that was drawn before
+    fn resize(&mut self, area: WorldRect, allegro_core: &allegro::Core) {
+        log::trace!("Resize map from {:?} to {:?}", self.area, area);
         allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
         let map = allegro::Bitmap::new(
             allegro_core,
-            world.clipping_rect.width() as i32,
-            world.clipping_rect.height() as i32,
+            area.width() as i32,
+            area.height() as i32,
         )
             .expect("Cannot create map texture");
         allegro_core.set_target_bitmap(Some(&map));
-        log::trace!("Number of islands {}", world.islands.len());
-        for x in 0..world.clipping_rect.width() as usize {
-            for y in 0..world.clipping_rect.height() as usize {
-                allegro_core.put_pixel(x as i32, y as i32, allegro::Color::from_rgba(255, 255, 255, 255));
+        allegro_core.clear_to_color(MapRenderer::color(self.palette.colors().unexplored));
+        if self.area.is_empty() {
+            self.rendered_chunks.clear();
+        }
+        else {
+            // copy pixels without blending
+            let offset = self.area.origin - area.origin;
+            allegro_core.set_blender(
+                allegro::core::BlendOperation::Add,
+                allegro::core::BlendMode::One,
+                allegro::core::BlendMode::Zero,
+            );
+            allegro_core.draw_bitmap(&self.map, offset.x, offset.y, allegro::core::FLIP_NONE);
+            allegro_core.set_blender(
+                allegro::core::BlendOperation::Add,
+                allegro::core::BlendMode::One,
+                allegro::core::BlendMode::InverseAlpha,
+            );
+        }
+        self.map = map;
+        self.area = area;
+    }
+
+    /// draw chunk `ind` to the current target bitmap
+    fn draw_chunk(&self, ind: ChunkIndex, world: &World, allegro_core: &allegro::Core) {
+        let colors = self.palette.colors();
+        let chunk = WorldRect::new(
+            WorldCoordinate::new(ind.x as f32 * CHUNK_SIZE, ind.y as f32 * CHUNK_SIZE),
+            WorldVector::new(CHUNK_SIZE, CHUNK_SIZE).to_size(),
+        );
+        let background = match self.layer {
+            MapLayer::Chunks => colors.chunks[(ind.x + ind.y).rem_euclid(2) as usize],
+            _ => colors.ocean,
+        };
+        let chunk_origin = chunk.origin - self.area.origin;
+        for x in 0..CHUNK_SIZE as usize {
+            for y in 0..CHUNK_SIZE as usize {
+                allegro_core.put_pixel(
+                    (chunk_origin.x + x as f32) as i32,
+                    (chunk_origin.y + y as f32) as i32,
+                    MapRenderer::color(background),
+                );
             }
         }
-        for ind in world.chunks.keys() {
-            for x in 0..CHUNK_SIZE as usize {
-                for y in 0..CHUNK_SIZE as usize {
-                    let pos = WorldCoordinate::new(
-                        ind.x as f32 * CHUNK_SIZE + x as f32,
-                        ind.y as f32 * CHUNK_SIZE + y as f32,
-                    ) - world.clipping_rect.origin;
-                    let color = allegro::Color::from_rgba(0, 255, 0, 128);
-                    allegro_core.put_pixel(pos.x as i32, pos.y as i32, color);
-                }
+        if self.layer == MapLayer::Chunks {
+            return;
+        }
+        for (island_index, island) in world.islands.iter().enumerate() {
+            if !island.clipping_rect.intersects(&chunk) {
+                continue;
+            }
+            // tiles of the island inside the chunk
+            let rel = chunk.translate(-island.clipping_rect.origin.to_vector());
+            let min = rel.min().max(WorldCoordinate::zero()).to_usize();
+            let max = rel.max().max(WorldCoordinate::zero()).to_usize();
+            for (index, _) in island.tiles.view(GridRect::new(min.cast_unit(), (max - min).to_size().cast_unit())).indexed_iter() {
+                let pos = island.tile_pos(index.x, index.y) - self.area.origin;
+                let color = self.tile_color(island, island_index, index.x, index.y);
+                allegro_core.put_pixel(pos.x as i32, pos.y as i32, MapRenderer::color(color));
             }
         }
-        for island in &world.islands {
-            for x in 0..island.tiles.len() {
-                for tile in &island.tiles[x] {
-                    let color: allegro::Color;
-                    if tile.height <= 0.0 {
-                        color = allegro::Color::from_rgba(0, 0, 255, 255);
-                    }
-                    else if tile.height <= 1.0 {
-                        color = allegro::Color::from_rgba(255, 255, 0, 255);
-                    }
-                    else if tile.height <= 2.0 {
-                        color = allegro::Color::from_rgba(255, 0, 255, 255);
-                    }
-                    else {
-                        color = allegro::Color::from_rgba(128, 128, 128, 255);
-                    }
-                    let pos = tile.pos - world.clipping_rect.origin;
-                    allegro_core.put_pixel(pos.x as i32, pos.y as i32, color);
+    }
+
+    /// color of tile `x`, `y` of the island with index `island_index`
+    fn tile_color(&self, island: &Island, island_index: usize, x: usize, y: usize) -> Rgb {
+        let colors = self.palette.colors();
+        let tile = &island.tiles[(x, y)];
+        match self.layer {
+            MapLayer::Height => {
+                let color = MapRenderer::height_color(colors, tile.height());
+                if x == 0 || y == 0 {
+                    return color;
                 }
+                let slope = tile.height() - island.tiles[(x - 1, y - 1)].height();
+                let shade = (1.0 + HILLSHADE * slope).clamp(0.5, 1.5);
+                color.map(|c| (c as f32 * shade).min(255.0) as u8)
             }
+            MapLayer::Biome => colors.biomes[tile.biome() as usize],
+            MapLayer::Resources => match tile.resource() {
+                Some(_) => colors.tree,
+                None if tile.height() > 0.0 => colors.land,
+                None => colors.ocean,
+            },
+            MapLayer::Ownership if tile.height() > 0.0 => colors.islands[island_index % colors.islands.len()],
+            MapLayer::Ownership => colors.ocean,
+            MapLayer::Chunks => colors.unexplored,
         }
-        allegro_core.set_target_bitmap(Some(allegro_display.get_backbuffer()));
-        allegro_core.set_new_bitmap_flags(allegro::VIDEO_BITMAP);
-        self.map = allegro_display.convert_bitmap(&map).unwrap();
-        self.map_size =
-            ScreenCoordinate::new(world.clipping_rect.width(), world.clipping_rect.height());
     }
-    pub fn draw(&self, allegro_core: &allegro::Core, pos: ScreenCoordinate, world: &World) {
-        let flags = allegro::core::FLIP_NONE;
-        allegro_core.draw_tinted_scaled_rotated_bitmap_region(
-            &self.map,
-            // texture start
-            0.0,
-            0.0,
-            // texture dimensions
-            self.map_size.x,
-            self.map_size.y,
-            allegro::Color::from_rgb_f(1.0, 1.0, 1.0),
-            0.0,
-            0.0,
-            // position
-            pos.x,
-            pos.y,
-            // scale
-            1.0,
-            1.0,
-            0.0,
-            flags,
-        );
-        allegro_core.draw_pixel(
-            // position
-            pos.x + world.screen_pos.x - world.clipping_rect.origin.x,
-            pos.y + world.screen_pos.y - world.clipping_rect.origin.y,
-            allegro::Color::from_rgb(255, 0, 0));
-        allegro_core.draw_pixel(
-            // position
-            pos.x + world.screen_pos.x - world.clipping_rect.origin.x-1.0,
-            pos.y + world.screen_pos.y - world.clipping_rect.origin.y,
-            allegro::Color::from_rgb(255, 0, 0));
-        allegro_core.draw_pixel(
-            // position
-            pos.x + world.screen_pos.x - world.clipping_rect.origin.x+1.0,
-            pos.y + world.screen_pos.y - world.clipping_rect.origin.y,
-            allegro::Color::from_rgb(255, 0, 0));
+
+    /// linear interpolation of the height ramp
+    fn height_color(colors: &Colors, height: f32) -> Rgb {
+        let max_index = colors.height.len() - 1;
+        let pos = height.clamp(0.0, max_index as f32);
+        let lower = (pos as usize).min(max_index - 1);
+        let t = pos - lower as f32;
+        let mut ret = [0; 3];
+        for (i, channel) in ret.iter_mut().enumerate() {
+            *channel = (colors.height[lower][i] as f32 * (1.0 - t) + colors.height[lower + 1][i] as f32 * t) as u8;
+        }
+        ret
+    }
+
+    fn color(rgb: Rgb) -> allegro::Color {
+        allegro::Color::from_rgb(rgb[0], rgb[1], rgb[2])
     }
 }
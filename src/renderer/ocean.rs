@@ -0,0 +1,248 @@
+//! Draws the sea below the terrain
+//!
+//! The sea is deep everywhere except on the shelf around islands, which gets shallower towards
+//! the coast. The shelf of each island is a bitmap with one pixel per tile that is drawn in
+//! isometric view with linear filtering, so depth changes smoothly. On top come animated water
+//! sprites and foam along the coast, which are not cached.
+use super::terrain;
+use crate::glob::types::*;
+use crate::grid::Grid;
+use crate::world::island::tile::Biome;
+use crate::world::island::Island;
+use crate::world::{chunk_index, World};
+use allegro::BitmapLike;
+use std::collections::HashMap;
+
+/// Colour of the open sea
+const DEEP_SEA: [f32; 3] = [0.05, 0.15, 0.4];
+/// Colour of water next to the coast
+const SHALLOW_SEA: [f32; 3] = [0.25, 0.65, 0.75];
+/// Distance from the coast in tiles at which the sea is deep
+const SHELF_WIDTH: usize = 12;
+/// Opacity of the water sprites on top of the depth colours
+const WATER_OPACITY: f32 = 0.35;
+const WATER_FPS: f32 = 6.0;
+/// Foam pulses per second, in radians
+const FOAM_SPEED: f32 = 2.0;
+/// Width of the foam at zoom 1 in pixels
+const FOAM_WIDTH: f32 = 3.0;
+
+/// Pre-computed sea around one island
+struct Shelf {
+    /// revision of the island's chunks this was computed at
+    revision: u64,
+    /// world position of the upper left pixel of `depth`
+    origin: WorldCoordinate,
+    /// shallow water colour, fading out with depth
+    depth: Option<allegro::Bitmap>,
+    /// edges between land and water tiles
+    coast: Vec<(WorldCoordinate, WorldCoordinate)>,
+}
+
+/// Shelves by island index
+pub struct Ocean {
+    shelves: HashMap<usize, Shelf>,
+    start: std::time::Instant,
+}
+
+impl Default for Ocean {
+    fn default() -> Self {
+        Ocean {
+            shelves: HashMap::new(),
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Ocean {
+    /// Draw the sea in `view`, covering the current target.
+    ///
+    /// Without `animate` only the depth is drawn. `water` is the animated water texture
+    pub fn draw(
+        &mut self,
+        allegro_core: &allegro::Core,
+        primitives_addon: &allegro_primitives::PrimitivesAddon,
+        water: &allegro::Bitmap,
+        world: &World,
+        view: &terrain::View,
+        animate: bool,
+    ) {
+        let [r, g, b] = DEEP_SEA;
+        allegro_core.clear_to_color(allegro::Color::from_rgb_f(r, g, b));
+        let shelf_margin = SHELF_WIDTH as f32;
+        let time = self.start.elapsed().as_secs_f32();
+        self.shelves.retain(|&index, _| index < world.islands.len());
+        for (index, island) in world.islands.iter().enumerate() {
+            let shelf_area = island.clipping_rect.inflate(shelf_margin, shelf_margin);
+            if !shelf_area.intersects(&view.world_area) {
+                continue;
+            }
+            let revision = island_revision(world, island);
+            if self.shelves.get(&index).is_none_or(|shelf| shelf.revision != revision) {
+                self.shelves.insert(index, Shelf::new(allegro_core, island, revision));
+            }
+            let shelf = &self.shelves[&index];
+            if let Some(depth) = &shelf.depth {
+                view.draw_iso_bitmap(allegro_core, depth, allegro::Color::from_rgb_f(1.0, 1.0, 1.0), shelf.origin);
+            }
+        }
+        if !animate {
+            return;
+        }
+
+        let frames = (water.get_width() / water.get_height()).max(1) as usize;
+        let frame = (time * WATER_FPS) as usize;
+        let tint = allegro::Color::from_rgba_f(WATER_OPACITY, WATER_OPACITY, WATER_OPACITY, WATER_OPACITY);
+        let first = view.world_area.min().floor();
+        let last = view.world_area.max().ceil();
+        let apparent_tile_size = crate::glob::TILE_SIZE * view.scale;
+        allegro_core.hold_bitmap_drawing(true);
+        for x in first.x as i32..last.x as i32 {
+            for y in first.y as i32..last.y as i32 {
+                let pos = WorldCoordinate::new(x as f32, y as f32);
+                let top = view.w2s.transform_point(pos);
+                if top.x < view.screen_area.min_x() - apparent_tile_size.x
+                    || top.y < view.screen_area.min_y() - apparent_tile_size.y
+                    || top.x >= view.screen_area.max_x() + apparent_tile_size.x
+                    || top.y >= view.screen_area.max_y()
+                {
+                    continue;
+                }
+                // neighbouring tiles are out of phase so the sea does not pulse as one
+                let phase = (x * 3 + y * 5).rem_euclid(frames as i32) as usize;
+                view.draw_tinted_diamond(allegro_core, water, (frame + phase) % frames, pos, 0, tint);
+            }
+        }
+        allegro_core.hold_bitmap_drawing(false);
+
+        for (index, shelf) in &self.shelves {
+            if !world.islands[*index].clipping_rect.intersects(&view.world_area) {
+                continue;
+            }
+            for (a, b) in &shelf.coast {
+                let alpha = 0.5 + 0.3 * (time * FOAM_SPEED + a.x + a.y).sin();
+                let (a, b) = (view.w2s.transform_point(*a), view.w2s.transform_point(*b));
+                primitives_addon.draw_line(
+                    a.x,
+                    a.y,
+                    b.x,
+                    b.y,
+                    allegro::Color::from_rgba_f(alpha, alpha, alpha, alpha),
+                    FOAM_WIDTH * view.scale,
+                );
+            }
+        }
+    }
+}
+
+impl Shelf {
+    fn new(allegro_core: &allegro::Core, island: &Island, revision: u64) -> Self {
+        let cols = island.tiles.width();
+        let rows = island.tiles.height();
+        let is_land = |x: isize, y: isize| island.tiles.at(x, y).is_some_and(|tile| tile.biome() != Biome::Water);
+
+        let mut coast = Vec::new();
+        for x in 0..cols as isize {
+            for y in 0..rows as isize {
+                if !is_land(x, y) {
+                    continue;
+                }
+                let pos = island.tile_pos(x as usize, y as usize);
+                let corner = |dx: f32, dy: f32| pos + WorldVector::new(dx, dy);
+                let edges = [
+                    ((0, -1), corner(0.0, 0.0), corner(1.0, 0.0)),
+                    ((1, 0), corner(1.0, 0.0), corner(1.0, 1.0)),
+                    ((0, 1), corner(0.0, 1.0), corner(1.0, 1.0)),
+                    ((-1, 0), corner(0.0, 0.0), corner(0.0, 1.0)),
+                ];
+                for ((dx, dy), a, b) in edges {
+                    if !is_land(x + dx, y + dy) {
+                        coast.push((a, b));
+                    }
+                }
+            }
+        }
+
+        // the island grid with a margin of the shelf width on every side
+        let width = cols + 2 * SHELF_WIDTH;
+        let height = rows + 2 * SHELF_WIDTH;
+        let offset = SHELF_WIDTH as isize;
+        let distance = distance_field(width, height, |x, y| is_land(x as isize - offset, y as isize - offset));
+
+        // the caller's target is restored afterwards, the crate offers no getter
+        let target = unsafe { allegro_sys::al_get_target_bitmap() };
+        let new_bitmap_flags = allegro_core.get_new_bitmap_flags();
+        allegro_core.set_new_bitmap_flags(allegro::MEMORY_BITMAP);
+        let depth = match allegro::Bitmap::new(allegro_core, width as i32, height as i32) {
+            Ok(memory) => {
+                allegro_core.set_target_bitmap(Some(&memory));
+                for (index, tile_distance) in distance.indexed_iter() {
+                    let alpha = (1.0 - tile_distance / SHELF_WIDTH as f32).clamp(0.0, 1.0);
+                    let [r, g, b] = SHALLOW_SEA.map(|c| c * alpha);
+                    allegro_core.put_pixel(index.x as i32, index.y as i32, allegro::Color::from_rgba_f(r, g, b, alpha));
+                }
+                // smooth between tiles
+                allegro_core.set_new_bitmap_flags(new_bitmap_flags | allegro::MIN_LINEAR | allegro::MAG_LINEAR);
+                Some(memory.clone())
+            }
+            Err(_) => {
+                log::error!("Cannot create shelf bitmap of size {} x {}", width, height);
+                None
+            }
+        };
+        allegro_core.set_new_bitmap_flags(new_bitmap_flags);
+        unsafe { allegro_sys::al_set_target_bitmap(target) };
+
+        Shelf {
+            revision,
+            origin: island.clipping_rect.origin - WorldVector::new(SHELF_WIDTH as f32, SHELF_WIDTH as f32),
+            depth,
+            coast,
+        }
+    }
+}
+
+/// Highest revision of the chunks `island` lies in
+fn island_revision(world: &World, island: &Island) -> u64 {
+    let first = chunk_index(island.clipping_rect.min());
+    let last = chunk_index(island.clipping_rect.max());
+    let mut revision = 0;
+    for x in first.x..last.x + 1 {
+        for y in first.y..last.y + 1 {
+            if let Some(chunk) = world.chunks.get(&euclid::default::Point2D::new(x, y)) {
+                revision = revision.max(chunk.revision);
+            }
+        }
+    }
+    revision
+}
+
+/// Approximate euclidean distance of every cell to the nearest cell for which `is_source` holds
+fn distance_field(width: usize, height: usize, is_source: impl Fn(usize, usize) -> bool) -> Grid<f32> {
+    let mut distance = Grid::from_fn(width, height, |x, y| if is_source(x, y) { 0.0 } else { f32::MAX });
+    // chamfer distance: one pass from the upper left, one from the lower right
+    let forward = [(-1, 0, 1.0), (0, -1, 1.0), (-1, -1, std::f32::consts::SQRT_2), (1, -1, std::f32::consts::SQRT_2)];
+    let backward = forward.map(|(dx, dy, d)| (-dx, -dy, d));
+    let mut relax = |x: usize, y: usize, offsets: &[(isize, isize, f32); 4]| {
+        for &(dx, dy, d) in offsets {
+            let candidate = match distance.at(x as isize + dx, y as isize + dy) {
+                Some(neighbour) => neighbour + d,
+                None => continue,
+            };
+            if candidate < distance[(x, y)] {
+                distance[(x, y)] = candidate;
+            }
+        }
+    };
+    for y in 0..height {
+        for x in 0..width {
+            relax(x, y, &forward);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            relax(x, y, &backward);
+        }
+    }
+    distance
+}
@@ -0,0 +1,107 @@
+//! World-space debug overlays drawn on top of the rendered world
+use super::engine::Engine;
+use crate::glob::types::*;
+use crate::world::{chunk_index, ChunkIndex, World, CHUNK_SIZE};
+use allegro_font::FontDrawing;
+
+/// Toggles for the individual overlays, set in the Debug tab of the GUI
+#[derive(Clone, Copy, Default)]
+pub struct DebugOverlays {
+    /// chunk boundaries labelled with their `ChunkIndex`
+    pub chunk_bounds: bool,
+    /// fill generated chunks green and pending chunks red
+    pub chunk_state: bool,
+    /// clipping rect of every island
+    pub island_rects: bool,
+    /// clipping rect of the world
+    pub world_rect: bool,
+    /// `rendered_world_area` versus `screen_on_world`
+    pub render_areas: bool,
+}
+
+const LINE_THICKNESS: f32 = 1.0;
+
+/// Draw all enabled overlays
+pub fn draw(
+    overlays: &DebugOverlays,
+    engine: &Engine,
+    world: &World,
+    w2s: &euclid::Transform2D<f32, WorldSpace, ScreenSpace>,
+    rendered_world_area: WorldRect,
+    screen_on_world: WorldRect,
+) {
+    if overlays.chunk_state || overlays.chunk_bounds {
+        let visible_area = rendered_world_area.union(&screen_on_world);
+        let start_chunk = chunk_index(visible_area.min());
+        let end_chunk = chunk_index(visible_area.max());
+        for x in start_chunk.x..end_chunk.x + 1 {
+            for y in start_chunk.y..end_chunk.y + 1 {
+                let ind = ChunkIndex::new(x, y);
+                let chunk = WorldRect::new(
+                    WorldCoordinate::new(x as f32 * CHUNK_SIZE, y as f32 * CHUNK_SIZE),
+                    WorldVector::new(CHUNK_SIZE, CHUNK_SIZE).to_size(),
+                );
+                let corners = screen_corners(w2s, chunk);
+                if overlays.chunk_state {
+                    // colors are premultiplied
+                    let color = if world.chunks.contains_key(&ind) {
+                        allegro::Color::from_rgba_f(0.0, 0.2, 0.0, 0.2)
+                    } else {
+                        allegro::Color::from_rgba_f(0.2, 0.0, 0.0, 0.2)
+                    };
+                    engine.primitives_addon.draw_filled_polygon(&corners, color);
+                }
+                if overlays.chunk_bounds {
+                    draw_rect(engine, w2s, chunk, allegro::Color::from_rgb(255, 255, 255));
+                    let center = w2s.transform_point(chunk.center());
+                    engine.core.draw_text(
+                        &engine.font,
+                        allegro::Color::from_rgb(255, 255, 255),
+                        center.x,
+                        center.y,
+                        allegro_font::FontAlign::Centre,
+                        &format!("{} {}", ind.x, ind.y),
+                    );
+                }
+            }
+        }
+    }
+    if overlays.island_rects {
+        for island in &world.islands {
+            draw_rect(engine, w2s, island.clipping_rect, allegro::Color::from_rgb(255, 255, 0));
+        }
+    }
+    if overlays.world_rect {
+        draw_rect(engine, w2s, world.clipping_rect, allegro::Color::from_rgb(255, 0, 255));
+    }
+    if overlays.render_areas {
+        draw_rect(engine, w2s, rendered_world_area, allegro::Color::from_rgb(0, 255, 255));
+        draw_rect(engine, w2s, screen_on_world, allegro::Color::from_rgb(255, 128, 0));
+    }
+}
+
+/// outline a world rectangle, which appears as a diamond on screen
+fn draw_rect(
+    engine: &Engine,
+    w2s: &euclid::Transform2D<f32, WorldSpace, ScreenSpace>,
+    rect: WorldRect,
+    color: allegro::Color,
+) {
+    engine.primitives_addon.draw_polygon(
+        &screen_corners(w2s, rect),
+        allegro_primitives::LineJoinType::Mitre,
+        color,
+        LINE_THICKNESS,
+        1.0,
+    );
+}
+
+fn screen_corners(
+    w2s: &euclid::Transform2D<f32, WorldSpace, ScreenSpace>,
+    rect: WorldRect,
+) -> [(f32, f32); 4] {
+    rect_corners(&rect).map(|corner| {
+        let p = w2s.transform_point(corner);
+        (p.x, p.y)
+    })
+}
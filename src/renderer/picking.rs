@@ -0,0 +1,128 @@
+//! Finds the tile drawn at a screen position
+//!
+//! A screen position is the end of a ray looking down into the world. Each level the ray descends,
+//! it moves half a tile away from the viewer. The ray is followed tile by tile from
+//! `terrain::MAX_LEVEL` down to sea level. The first tile the ray drops below, through its top
+//! or its cliff face, is the one drawn in front at that position.
+use super::terrain::{self, View};
+use crate::glob;
+use crate::glob::types::*;
+use crate::grid::GridIndex;
+use crate::world::World;
+
+/// Tile hit by a ray
+pub struct Picked {
+    pub island: usize,
+    pub index: GridIndex,
+    /// world position where the ray hits the tile, always inside the tile
+    pub pos: WorldCoordinate,
+}
+
+/// Tile drawn at `screen` in `view`. `None` if the ray reaches sea level outside of all islands
+pub fn pick(world: &World, view: &View, screen: ScreenCoordinate) -> Option<Picked> {
+    let s2w = view.w2s.inverse()?;
+    // world position under `screen` on the plane `level` levels above the sea
+    let at_level = |level: f32| {
+        s2w.transform_point(screen + ScreenVector::new(0.0, level * glob::LEVEL_OFFSET * view.scale))
+    };
+    let top_level = terrain::MAX_LEVEL as f32;
+    let start = at_level(top_level);
+    let dir = at_level(0.0) - start;
+
+    // grid traversal: `t_max` is the ray parameter at the next cell border on each axis
+    let mut cell = start.floor();
+    let step = WorldVector::new(dir.x.signum(), dir.y.signum());
+    let border = |pos: f32, cell: f32, dir: f32| {
+        if dir == 0.0 {
+            f32::INFINITY
+        }
+        else {
+            (cell + if dir > 0.0 { 1.0 } else { 0.0 } - pos) / dir
+        }
+    };
+    let mut t_max = WorldVector::new(border(start.x, cell.x, dir.x), border(start.y, cell.y, dir.y));
+    let t_delta = WorldVector::new(1.0 / dir.x.abs(), 1.0 / dir.y.abs());
+    loop {
+        let t_exit = t_max.x.min(t_max.y).min(1.0);
+        let center = cell + WorldVector::new(0.5, 0.5);
+        if let Some((island, index)) = world.tile_at(center) {
+            let level = world.islands[island].tiles[index].level() as f32;
+            // the ray is lowest where it leaves the tile
+            if level >= top_level * (1.0 - t_exit) {
+                // clamped to the front edge if the ray passes through the cliff face
+                let pos = at_level(level).clamp(cell, cell + WorldVector::new(0.999, 0.999));
+                return Some(Picked { island, index, pos });
+            }
+        }
+        if t_exit >= 1.0 {
+            return None;
+        }
+        if t_max.x < t_max.y {
+            cell.x += step.x;
+            t_max.x += t_delta.x;
+        }
+        else {
+            cell.y += step.y;
+            t_max.y += t_delta.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::world::island::tile::Tile;
+    use crate::world::island::{Island, IslandParams};
+
+    /// 4 x 4 island at the world origin on level 1, with tile `raised` on level 4 if given
+    fn world(raised: Option<(usize, usize)>) -> World {
+        let tiles = Grid::from_fn(4, 4, |x, y| Tile::new(if Some((x, y)) == raised { 2.0 } else { 0.5 }));
+        let mut world = World::default();
+        world.islands.push(Island {
+            clipping_rect: WorldRect::new(WorldCoordinate::zero(), euclid::default::Size2D::new(4.0, 4.0).cast_unit()),
+            tiles,
+            params: IslandParams { seed: 0, randmap_exp: 3, interpolation_scale: 8 },
+        });
+        world
+    }
+
+    /// Unrotated view at zoom 1 with the world origin at the screen origin
+    fn view() -> View {
+        View {
+            w2s: gen_w2s_matrix(1.0, WorldCoordinate::zero(), ScreenCoordinate::zero(), Rotation::default()),
+            world_area: WorldRect::new(WorldCoordinate::zero(), euclid::default::Size2D::new(4.0, 4.0).cast_unit()),
+            screen_area: ScreenRect::new(ScreenCoordinate::new(-200.0, -100.0), euclid::default::Size2D::new(400.0, 200.0).cast_unit()),
+            scale: 1.0,
+            rotation: Rotation::default(),
+        }
+    }
+
+    #[test]
+    fn raised_tile_hides_tile_behind() {
+        // the top of tile 1, 1 on level 1 is drawn here
+        let screen = ScreenCoordinate::new(0.0, 20.0);
+        let picked = pick(&world(None), &view(), screen).unwrap();
+        assert_eq!(picked.index, GridIndex::new(1, 1));
+        // the top of tile 2, 2 on level 4 covers it
+        let picked = pick(&world(Some((2, 2))), &view(), screen).unwrap();
+        assert_eq!(picked.index, GridIndex::new(2, 2));
+    }
+
+    #[test]
+    fn cliff_face_picks_its_tile() {
+        // below the front corner of the raised tile's top, on its cliff face
+        let screen = ScreenCoordinate::new(4.0, 50.0);
+        let picked = pick(&world(Some((2, 2))), &view(), screen).unwrap();
+        assert_eq!(picked.index, GridIndex::new(2, 2));
+        // the ray is in front of the tile on the tile's level, the hit is moved onto it
+        let tile = WorldRect::new(WorldCoordinate::new(2.0, 2.0), euclid::default::Size2D::new(1.0, 1.0).cast_unit());
+        assert!(tile.contains(picked.pos), "{:?} outside of the tile", picked.pos);
+    }
+
+    #[test]
+    fn sea_outside_islands_is_none() {
+        assert!(pick(&world(Some((2, 2))), &view(), ScreenCoordinate::new(0.0, -200.0)).is_none());
+        assert!(pick(&world(None), &view(), ScreenCoordinate::new(300.0, 40.0)).is_none());
+    }
+}
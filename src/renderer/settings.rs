@@ -1,11 +1,14 @@
-use crate::glob::types::{ScreenCoordinate};
+use crate::glob::types::{Rotation, ScreenCoordinate};
 
 #[derive(Clone, Copy)]
 pub struct Settings
 {
     pub fps: f32,
     pub screen_size: ScreenCoordinate,
+    /// initial zoom of the camera
     pub scale: f32,
+    /// initial orientation of the camera
+    pub rotation: Rotation,
 }
 
 impl Default for Settings {
@@ -14,6 +17,7 @@ impl Default for Settings {
             fps: 60.0,
             screen_size: ScreenCoordinate::new(1280.0, 720.0),
             scale: 1.0,
+            rotation: Rotation::default(),
         }
     }
 }
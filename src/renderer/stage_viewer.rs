@@ -0,0 +1,145 @@
+//! Window stepping through the stages of island generation
+//!
+//! Every stage, the heightmap after an operation of the island's recipe, is shown as an image
+//! scaled to the stage's value range, with water tinted blue, next to a histogram of its values.
+//! The stages are generated again whenever the parameters change, so their effect shows immediately.
+use crate::world::island::heightmap::{Heightmap, Pipeline};
+use crate::world::island::{Island, IslandParams};
+
+/// Histogram bars
+const BINS: usize = 32;
+/// Edge length of the longer image side in points
+const IMAGE_SIZE: f32 = 256.0;
+const HISTOGRAM_HEIGHT: f32 = 64.0;
+
+/// One generated stage ready to show
+struct StageImage {
+    /// name of the operation
+    stage: &'static str,
+    texture: egui::TextureHandle,
+    width: usize,
+    height: usize,
+    min: f32,
+    max: f32,
+    histogram: [usize; BINS],
+}
+
+#[derive(Default)]
+pub struct StageViewer {
+    /// parameters and recipe the stages were generated from
+    source: Option<(IslandParams, Pipeline)>,
+    stages: Vec<StageImage>,
+    /// index of the shown stage in `stages`
+    current: usize,
+}
+
+impl StageViewer {
+    /// Show the stages of the island generated from `params` with `recipe`, generating them first if they changed
+    pub fn show(&mut self, ui: &mut egui::Ui, params: IslandParams, recipe: Pipeline) {
+        let changed = match &self.source {
+            Some((old_params, old_recipe)) => *old_params != params || *old_recipe != recipe,
+            None => true,
+        };
+        if changed {
+            self.stages = Island::generation_stages(params, &recipe)
+                .into_iter()
+                .map(|(op, map)| StageImage::new(ui.ctx(), op.name(), &map))
+                .collect();
+            self.source = Some((params, recipe));
+        }
+        if self.stages.is_empty() {
+            return;
+        }
+        self.current = self.current.min(self.stages.len() - 1);
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.current = self.current.saturating_sub(1);
+            }
+            for (i, image) in self.stages.iter().enumerate() {
+                ui.selectable_value(&mut self.current, i, image.stage);
+            }
+            if ui.button(">").clicked() {
+                self.current = (self.current + 1).min(self.stages.len() - 1);
+            }
+        });
+        self.stages[self.current].show(ui);
+    }
+}
+
+impl StageImage {
+    fn new(ctx: &egui::Context, stage: &'static str, map: &Heightmap) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let values = || map.iter().copied();
+        let min = values().fold(f32::MAX, f32::min);
+        let max = values().fold(f32::MIN, f32::max);
+        let range = (max - min).max(f32::EPSILON);
+        let mut histogram = [0; BINS];
+        for value in values() {
+            histogram[(((value - min) / range * BINS as f32) as usize).min(BINS - 1)] += 1;
+        }
+        let pixels: Vec<egui::Color32> = values()
+            .map(|value| {
+                let brightness = ((value - min) / range * 255.0) as u8;
+                if value > 0.0 {
+                    egui::Color32::from_gray(brightness)
+                } else {
+                    egui::Color32::from_rgb(0, brightness / 2, brightness.max(64))
+                }
+            })
+            .collect();
+        let image = egui::ColorImage {
+            size: [width.max(1), height.max(1)],
+            pixels: if pixels.is_empty() { vec![egui::Color32::BLACK] } else { pixels },
+        };
+        StageImage {
+            stage,
+            // nearest filtering keeps the cells of small stages visible
+            texture: ctx.load_texture(stage, image, egui::TextureFilter::Nearest),
+            width,
+            height,
+            min,
+            max,
+            histogram,
+        }
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("StageInfo").num_columns(2).show(ui, |ui| {
+            ui.label("Size");
+            ui.label(format!("{} x {}", self.width, self.height));
+            ui.end_row();
+            ui.label("Range");
+            ui.label(format!("{:.3} .. {:.3}", self.min, self.max));
+            ui.end_row();
+        });
+        let scale = IMAGE_SIZE / self.width.max(self.height).max(1) as f32;
+        ui.image(&self.texture, egui::Vec2::new(self.width as f32 * scale, self.height as f32 * scale));
+
+        let (rect, response) = ui.allocate_exact_size(egui::Vec2::new(IMAGE_SIZE, HISTOGRAM_HEIGHT), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_gray(24));
+        let highest = *self.histogram.iter().max().unwrap_or(&1).max(&1) as f32;
+        let bar_width = rect.width() / BINS as f32;
+        for (i, count) in self.histogram.iter().enumerate() {
+            let bar_height = *count as f32 / highest * rect.height();
+            let min = egui::Pos2::new(rect.min.x + i as f32 * bar_width, rect.max.y - bar_height);
+            let bar = egui::Rect::from_min_size(min, egui::Vec2::new(bar_width - 1.0, bar_height));
+            painter.rect_filled(bar, 0.0, egui::Color32::LIGHT_GRAY);
+        }
+        // sea level
+        if self.min < 0.0 && self.max > 0.0 {
+            let x = rect.min.x + -self.min / (self.max - self.min) * rect.width();
+            painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, egui::Color32::LIGHT_BLUE));
+        }
+        if let Some(pos) = response.hover_pos() {
+            let bin = (((pos.x - rect.min.x) / bar_width) as usize).min(BINS - 1);
+            let bin_width = (self.max - self.min) / BINS as f32;
+            response.on_hover_text(format!(
+                "{:.3} .. {:.3}: {}",
+                self.min + bin as f32 * bin_width,
+                self.min + (bin + 1) as f32 * bin_width,
+                self.histogram[bin]
+            ));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Draws the tiles of a `World`
+//!
+//! Tiles are raised by their elevation level. Where a tile is higher than its neighbour in front,
+//! a cliff face fills the gap. Tiles are drawn diagonal by diagonal from back to front as seen from
+//! the current camera rotation, so nearer tiles and cliffs cover the ones behind them. Water tiles are transparent apart from transitions,
+//! the sea is drawn below by `ocean`.
+use super::autotile::{self, Transitions};
+use super::engine::TextureType;
+use crate::glob;
+use crate::glob::types::*;
+use crate::world::island::tile::{Biome, Tile, LEVEL_HEIGHT, MAX_HEIGHT};
+use crate::world::island::Island;
+use crate::world::World;
+use allegro::BitmapLike;
+use std::rc::Rc;
+
+/// Level of tiles at `MAX_HEIGHT`
+pub const MAX_LEVEL: i32 = (MAX_HEIGHT / LEVEL_HEIGHT) as i32;
+/// Tiles outside of the rendered area that may be raised into it, in tiles. Each level raises a
+/// tile by half a tile
+pub const ELEVATION_MARGIN: f32 = MAX_LEVEL as f32 / 2.0;
+/// Brightness of the cliff faces facing left and right on screen
+const CLIFF_SHADE: [f32; 2] = [0.55, 0.75];
+/// Tile corners in tile coordinates, clockwise
+const VERTICES: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+/// Neighbours sharing an edge with a tile. Edge `i` runs from vertex `i` to the next one
+const EDGE_NEIGHBOURS: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// How tiles are drawn
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Detail {
+    /// textures with transitions
+    Sprites,
+    /// diamonds in the average colour of the texture
+    Flat,
+}
+
+/// Part of the world to draw and where to draw it
+pub struct View {
+    pub w2s: euclid::Transform2D<f32, WorldSpace, ScreenSpace>,
+    /// tiles outside of this area are skipped. Raised tiles may be visible above it, see `ELEVATION_MARGIN`
+    pub world_area: WorldRect,
+    /// area of the target bitmap to draw to
+    pub screen_area: ScreenRect,
+    pub scale: f32,
+    pub rotation: Rotation,
+}
+
+impl View {
+    /// screen position of world position `pos` raised to elevation `level`
+    pub fn elevated(&self, pos: WorldCoordinate, level: i32) -> ScreenCoordinate {
+        self.w2s.transform_point(pos) - ScreenVector::new(0.0, level as f32 * glob::LEVEL_OFFSET * self.scale)
+    }
+    /// draw frame `frame` of the flat diamond texture `bitmap` on top of the tile at `pos` raised to `level`
+    pub fn draw_diamond(
+        &self,
+        allegro_core: &allegro::Core,
+        bitmap: &allegro::Bitmap,
+        frame: usize,
+        pos: WorldCoordinate,
+        level: i32,
+    ) {
+        self.draw_tinted_diamond(allegro_core, bitmap, frame, pos, level, allegro::Color::from_rgb_f(1.0, 1.0, 1.0));
+    }
+    /// `draw_diamond` tinted with `tint`
+    pub fn draw_tinted_diamond(
+        &self,
+        allegro_core: &allegro::Core,
+        bitmap: &allegro::Bitmap,
+        frame: usize,
+        pos: WorldCoordinate,
+        level: i32,
+        tint: allegro::Color,
+    ) {
+        // diamond textures are square, strips hold several frames side by side
+        let texture_len = bitmap.get_height() as f32;
+        let top = self.elevated(pos + self.rotation.tile_top(), level);
+        allegro_core.draw_tinted_scaled_rotated_bitmap_region(
+            bitmap,
+            // texture start
+            frame as f32 * texture_len,
+            0.0,
+            // texture dimensions
+            texture_len,
+            texture_len,
+            tint,
+            0.0,
+            0.0,
+            // position
+            top.x - glob::TILE_SIZE.x * self.scale / 2.0,
+            top.y,
+            glob::TILE_SIZE.x * self.scale / texture_len,
+            glob::TILE_SIZE.y * self.scale / texture_len,
+            0.0,
+            allegro::core::FLIP_NONE,
+        );
+    }
+    /// draw `bitmap` tinted with `tint` at sea level with one pixel per tile, its upper left pixel at `origin`
+    pub fn draw_iso_bitmap(
+        &self,
+        allegro_core: &allegro::Core,
+        bitmap: &allegro::Bitmap,
+        tint: allegro::Color,
+        origin: WorldCoordinate,
+    ) {
+        let m2s = euclid::Transform2D::<f32, WorldSpace, WorldSpace>::translation(origin.x, origin.y).then(&self.w2s);
+        let mut transform = allegro::Transform::identity();
+        let matrix = transform.get_matrix_mut();
+        matrix[0][0] = m2s.m11;
+        matrix[0][1] = m2s.m12;
+        matrix[1][0] = m2s.m21;
+        matrix[1][1] = m2s.m22;
+        matrix[3][0] = m2s.m31;
+        matrix[3][1] = m2s.m32;
+        allegro_core.use_transform(&transform);
+        allegro_core.draw_tinted_bitmap(bitmap, tint, 0.0, 0.0, allegro::core::FLIP_NONE);
+        allegro_core.use_transform(&allegro::Transform::identity());
+    }
+}
+
+/// Colour of a flat land tile of `biome`
+fn flat_color(biome: Biome) -> allegro::Color {
+    match biome {
+        Biome::Water | Biome::Sand => allegro::Color::from_rgb_f(0.85, 0.8, 0.55),
+        Biome::Grass => allegro::Color::from_rgb_f(0.35, 0.6, 0.25),
+        Biome::Rock => allegro::Color::from_rgb_f(0.55, 0.55, 0.55),
+    }
+}
+
+/// Colour of the cliff below a tile of `biome`
+fn cliff_color(biome: Biome, shade: f32) -> allegro::Color {
+    let (r, g, b) = match biome {
+        Biome::Water => (0.1, 0.3, 0.6),
+        Biome::Sand => (0.75, 0.65, 0.45),
+        Biome::Grass => (0.45, 0.35, 0.25),
+        Biome::Rock => (0.45, 0.45, 0.45),
+    };
+    allegro::Color::from_rgb_f(r * shade, g * shade, b * shade)
+}
+
+/// Terrain of the tile at `x`, `y`. Outside of the island is water
+fn biome_at(island: &Island, x: isize, y: isize) -> Biome {
+    island.tiles.at(x, y).map_or(Biome::Water, Tile::biome)
+}
+
+/// Elevation of the tile at `x`, `y`. Outside of the island is sea level
+fn level_at(island: &Island, x: isize, y: isize) -> i32 {
+    island.tiles.at(x, y).map_or(0, Tile::level)
+}
+
+/// Draw all tiles in `view` to the current target bitmap. Returns the number of drawn tiles
+///
+/// Cliffs are primitives, so bitmap drawing must not be held.
+pub fn draw(
+    allegro_core: &allegro::Core,
+    primitives_addon: &allegro_primitives::PrimitivesAddon,
+    bitmaps: &[Rc<allegro::Bitmap>],
+    transitions: &Transitions,
+    world: &World,
+    view: &View,
+    detail: Detail,
+) -> usize {
+    let mut drawn_cells = 0;
+    let apparent_tile_size = glob::TILE_SIZE * view.scale;
+    // islands further back first
+    let mut islands: Vec<&Island> = world.islands
+        .iter()
+        .filter(|island| island.clipping_rect.intersects(&view.world_area))
+        .collect();
+    islands.sort_by(|a, b| {
+        let a = view.rotation.depth(a.clipping_rect.center().to_vector());
+        let b = view.rotation.depth(b.clipping_rect.center().to_vector());
+        a.total_cmp(&b)
+    });
+    // walk the grid mirrored where the camera looks against its axes
+    let flip_x = view.rotation.depth(WorldVector::new(1.0, 0.0)) < 0.0;
+    let flip_y = view.rotation.depth(WorldVector::new(0.0, 1.0)) < 0.0;
+
+    for island in islands {
+        let cols = island.tiles.width();
+        let rows = island.tiles.height();
+        if cols == 0 || rows == 0 {
+            continue;
+        }
+        // all tiles on a diagonal are side by side on screen
+        for diagonal in 0..(cols + rows - 1) {
+            let first_x = diagonal.saturating_sub(rows - 1);
+            for walk_x in first_x..=diagonal.min(cols - 1) {
+                let walk_y = diagonal - walk_x;
+                let x = if flip_x { cols - 1 - walk_x } else { walk_x };
+                let y = if flip_y { rows - 1 - walk_y } else { walk_y };
+                let tile = &island.tiles[(x, y)];
+                let pos = island.tile_pos(x, y);
+                if !view.world_area.contains(pos) {
+                    continue;
+                }
+                let level = tile.level();
+                let top = view.elevated(pos + view.rotation.tile_top(), level);
+                // skip if tile is out of screen
+                if top.x < view.screen_area.min_x() - apparent_tile_size.x
+                    || top.y < view.screen_area.min_y() - apparent_tile_size.y
+                    || top.x >= view.screen_area.max_x() + apparent_tile_size.x
+                    || top.y >= view.screen_area.max_y()
+                {
+                    continue;
+                }
+                draw_cliffs(primitives_addon, island, x, y, view);
+                // water is left to the ocean below the terrain
+                let is_water = tile.biome() == Biome::Water;
+                match detail {
+                    Detail::Sprites => {
+                        let texture = TextureType::from_biome(tile.biome()) as usize;
+                        if !is_water {
+                            view.draw_diamond(allegro_core, &bitmaps[texture], 0, pos, level);
+                        }
+                        draw_transitions(allegro_core, transitions, texture, island, x, y, view);
+                    }
+                    Detail::Flat if is_water => (),
+                    Detail::Flat => {
+                        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+                            .map(|(dx, dy)| view.elevated(pos + WorldVector::new(dx, dy), level))
+                            .map(|corner| (corner.x, corner.y));
+                        primitives_addon.draw_filled_polygon(&corners, flat_color(tile.biome()));
+                    }
+                }
+                drawn_cells += 1;
+            }
+        }
+    }
+    drawn_cells
+}
+
+/// Draw the cliffs on the two front edges of the tile at `x`, `y` down to the neighbours
+fn draw_cliffs(
+    primitives_addon: &allegro_primitives::PrimitivesAddon,
+    island: &Island,
+    x: usize,
+    y: usize,
+    view: &View,
+) {
+    let tile = &island.tiles[(x, y)];
+    let pos = island.tile_pos(x, y);
+    let level = tile.level();
+    let center = view.w2s.transform_point(pos + WorldVector::new(0.5, 0.5));
+    for (edge, (dx, dy)) in EDGE_NEIGHBOURS.into_iter().enumerate() {
+        // only edges towards the viewer are visible
+        if view.rotation.depth(WorldVector::new(dx as f32, dy as f32)) <= 0.0 {
+            continue;
+        }
+        let neighbour_level = level_at(island, x as isize + dx, y as isize + dy);
+        if neighbour_level >= level {
+            continue;
+        }
+        let [a, b] = [VERTICES[edge], VERTICES[(edge + 1) % 4]].map(|(cx, cy)| pos + WorldVector::new(cx, cy));
+        let upper = [view.elevated(a, level), view.elevated(b, level)];
+        let lower = [view.elevated(b, neighbour_level), view.elevated(a, neighbour_level)];
+        let faces_right = upper[0].x + upper[1].x > 2.0 * center.x;
+        primitives_addon.draw_filled_polygon(
+            &[
+                (upper[0].x, upper[0].y),
+                (upper[1].x, upper[1].y),
+                (lower[0].x, lower[0].y),
+                (lower[1].x, lower[1].y),
+            ],
+            cliff_color(tile.biome(), CLIFF_SHADE[faces_right as usize]),
+        );
+    }
+}
+
+/// Draw the transition sprites of all textures that blend onto the tile at `x`, `y` with texture `texture`
+fn draw_transitions(
+    allegro_core: &allegro::Core,
+    transitions: &Transitions,
+    texture: usize,
+    island: &Island,
+    x: usize,
+    y: usize,
+    view: &View,
+) {
+    let tile = &island.tiles[(x, y)];
+    let pos = island.tile_pos(x, y);
+    // sprites are drawn as seen on screen, so look up the neighbours in that direction
+    let screen_to_world = view.rotation.inverse();
+    for set in transitions.sets.iter().filter(|set| set.over.contains(&texture)) {
+        let mut mask = 0u8;
+        for (bit, (dx, dy)) in autotile::NEIGHBOURS.iter().enumerate() {
+            let offset = screen_to_world.apply(WorldVector::new(*dx as f32, *dy as f32));
+            let neighbour = biome_at(island, x as isize + offset.x as isize, y as isize + offset.y as isize);
+            if TextureType::from_biome(neighbour) as usize == set.texture {
+                mask |= 1 << bit;
+            }
+        }
+        for sprite in autotile::sprites(mask) {
+            view.draw_diamond(allegro_core, &set.sprites, sprite, pos, tile.level());
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Sets of tiles picked by the user
+//!
+//! A selection is dragged out as a rectangle or a lasso, or stamped with a brush around the cursor.
+//! It holds world tile positions rather than island indices, so any tool can act on it and it
+//! stays meaningful when islands change.
+use crate::glob::types::*;
+use std::collections::HashSet;
+
+/// Position of a tile in the world grid, its upper left corner
+pub type TilePos = euclid::default::Point2D<isize>;
+
+/// Screen distance in pixels the mouse has to move while pressed to start a drag instead of a click
+const DRAG_THRESHOLD: f32 = 4.0;
+/// Minimum distance in tiles between two points of a lasso
+const LASSO_STEP: f32 = 0.25;
+
+/// Tile containing world position `pos`
+pub fn tile_pos(pos: WorldCoordinate) -> TilePos {
+    TilePos::new(pos.x.floor() as isize, pos.y.floor() as isize)
+}
+
+/// World position of the centre of the tile at `pos`
+pub fn tile_center(pos: TilePos) -> WorldCoordinate {
+    WorldCoordinate::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5)
+}
+
+/// Shape dragged out with the mouse
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SelectionShape {
+    #[default]
+    Rectangle,
+    Lasso,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BrushShape {
+    #[default]
+    Square,
+    Circle,
+}
+
+/// Tiles around the cursor affected by a click
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Brush {
+    pub shape: BrushShape,
+    /// distance from the centre tile in tiles, 0 is a single tile
+    pub radius: usize,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Selection {
+    tiles: HashSet<TilePos>,
+}
+
+impl Selection {
+    /// All tiles between the tiles containing `a` and `b`, inclusive
+    pub fn rectangle(a: WorldCoordinate, b: WorldCoordinate) -> Self {
+        let (a, b) = (tile_pos(a), tile_pos(b));
+        let (min, max) = (a.min(b), a.max(b));
+        let mut tiles = HashSet::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                tiles.insert(TilePos::new(x, y));
+            }
+        }
+        Selection { tiles }
+    }
+
+    /// Tiles whose centre lies inside the closed polygon `path`
+    pub fn lasso(path: &[WorldCoordinate]) -> Self {
+        let mut tiles = HashSet::new();
+        if path.len() < 3 {
+            return Selection { tiles };
+        }
+        let bounds = WorldRect::from_points(path.iter().copied());
+        let (min, max) = (tile_pos(bounds.min()), tile_pos(bounds.max()));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                if polygon_contains(path, tile_center(TilePos::new(x, y))) {
+                    tiles.insert(TilePos::new(x, y));
+                }
+            }
+        }
+        Selection { tiles }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = TilePos> + '_ {
+        self.tiles.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// World area covered by the selected tiles
+    pub fn bounds(&self) -> WorldRect {
+        WorldRect::from_points(self.tiles().flat_map(|pos| {
+            let min = WorldCoordinate::new(pos.x as f32, pos.y as f32);
+            [min, min + WorldVector::new(1.0, 1.0)]
+        }))
+    }
+}
+
+impl Brush {
+    /// Tiles covered by the brush centred on the tile containing `pos`
+    pub fn selection(&self, pos: WorldCoordinate) -> Selection {
+        let center = tile_pos(pos);
+        let radius = self.radius as isize;
+        let mut tiles = HashSet::new();
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                let inside = match self.shape {
+                    BrushShape::Square => true,
+                    // tiles whose centre is within half a tile of the radius
+                    BrushShape::Circle => ((dx * dx + dy * dy) as f32).sqrt() <= radius as f32 + 0.5,
+                };
+                if inside {
+                    tiles.insert(center + euclid::default::Vector2D::new(dx, dy));
+                }
+            }
+        }
+        Selection { tiles }
+    }
+}
+
+/// Selection being dragged out with the left mouse button
+pub struct Drag {
+    pub shape: SelectionShape,
+    /// screen position the drag started at
+    start: ScreenCoordinate,
+    /// world positions the cursor passed. Only the first and the last for rectangles
+    path: Vec<WorldCoordinate>,
+    /// true once the cursor moved far enough to not be a click
+    moved: bool,
+}
+
+impl Drag {
+    pub fn new(shape: SelectionShape, screen: ScreenCoordinate, pos: WorldCoordinate) -> Self {
+        Drag {
+            shape,
+            start: screen,
+            path: vec![pos],
+            moved: false,
+        }
+    }
+
+    /// Follow the cursor to `screen`, which is above world position `pos`
+    pub fn extend(&mut self, screen: ScreenCoordinate, pos: WorldCoordinate) {
+        self.moved |= (screen - self.start).length() >= DRAG_THRESHOLD;
+        match self.shape {
+            SelectionShape::Rectangle => {
+                self.path.truncate(1);
+                self.path.push(pos);
+            }
+            SelectionShape::Lasso => {
+                let last = *self.path.last().unwrap();
+                if (pos - last).length() >= LASSO_STEP {
+                    self.path.push(pos);
+                }
+            }
+        }
+    }
+
+    /// True as long as the mouse stayed close to where it was pressed
+    pub fn is_click(&self) -> bool {
+        !self.moved
+    }
+
+    pub fn start(&self) -> WorldCoordinate {
+        self.path[0]
+    }
+
+    /// World positions the lasso passed
+    pub fn path(&self) -> &[WorldCoordinate] {
+        &self.path
+    }
+
+    /// Tiles inside the dragged shape
+    pub fn selection(&self) -> Selection {
+        match self.shape {
+            SelectionShape::Rectangle => Selection::rectangle(self.path[0], *self.path.last().unwrap()),
+            SelectionShape::Lasso => Selection::lasso(&self.path),
+        }
+    }
+}
+
+/// Even-odd rule point in polygon test
+fn polygon_contains(polygon: &[WorldCoordinate], point: WorldCoordinate) -> bool {
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &current in polygon {
+        if (current.y > point.y) != (previous.y > point.y) {
+            let crossing_x = previous.x + (point.y - previous.y) / (current.y - previous.y) * (current.x - previous.x);
+            if point.x < crossing_x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
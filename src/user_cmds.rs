@@ -1,4 +1,9 @@
+use crate::editor::EditTool;
 use crate::glob::*;
+use crate::selection::{tile_center, Selection, TilePos};
+use crate::world::island::tile::{Biome, Building};
+use crate::world::island::IslandParams;
+use crate::world::World;
 pub const NUM_KEYS: usize = 119;
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum KeyState {
@@ -11,6 +16,16 @@ pub struct RendererFeedback {
     pub key_states: [KeyState; NUM_KEYS],
     pub loaded_world_area: types::WorldRect,
     pub update_necessary: bool,
+    /// tool selected in the GUI
+    pub tool: Tool,
+    /// tiles dragged out or clicked with the brush this frame, for `tool` to act on
+    pub selection: Option<Selection>,
+    /// strength of editor tools, see `Editor::apply`
+    pub brush_strength: f32,
+    /// undo and redo requests in the order they were made
+    pub history: Vec<HistoryCmd>,
+    /// island index and the parameters to regenerate it from
+    pub regenerate_island: Option<(usize, IslandParams)>,
 }
 
 impl Default for RendererFeedback {
@@ -21,6 +36,11 @@ impl Default for RendererFeedback {
             key_states: [KeyState::Released; NUM_KEYS],
             loaded_world_area: types::WorldRect::default(),
             update_necessary: false,
+            tool: Tool::default(),
+            selection: None,
+            brush_strength: 1.0,
+            history: Vec::new(),
+            regenerate_island: None,
         }
     }
 }
@@ -31,7 +51,10 @@ pub struct MouseState {
     pub middle: bool,
     pub right: bool,
     pub pos: types::WorldCoordinate,
-    pub pos_diff: types::WorldVector,
+    /// world position the left button was pressed at while it is dragged
+    pub drag_start: Option<types::WorldCoordinate>,
+    /// world position a left drag ended at, only in the frame it ended
+    pub drag_end: Option<types::WorldCoordinate>,
 }
 
 impl Default for MouseState {
@@ -41,7 +64,40 @@ impl Default for MouseState {
             middle: false,
             right: false,
             pos: types::WorldCoordinate::new(0.0, 0.0),
-            pos_diff: types::WorldVector::new(0.0, 0.0),
+            drag_start: None,
+            drag_end: None,
+        }
+    }
+}
+
+/// What the user acts with on selected tiles
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Tool {
+    /// look at tiles without changing them
+    #[default]
+    Inspect,
+    /// place a building on free land
+    Build(Building),
+    /// change the terrain in designer mode
+    Edit(EditTool),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HistoryCmd {
+    Undo,
+    Redo,
+}
+
+impl Tool {
+    /// True if the tool can act on the tile at `pos`
+    pub fn is_valid(&self, world: &World, pos: TilePos) -> bool {
+        let tile = world.tile(tile_center(pos));
+        match self {
+            Tool::Inspect => tile.is_some(),
+            Tool::Build(_) => tile.is_some_and(|tile| {
+                tile.biome() != Biome::Water && tile.building().is_none() && tile.resource().is_none()
+            }),
+            Tool::Edit(edit_tool) => edit_tool.is_valid(world, pos),
         }
     }
 }
@@ -1,22 +1,52 @@
 use crate::glob::types::*;
+use crate::grid::GridIndex;
 use rand::distributions::Distribution;
 pub const CHUNK_SIZE: f32 = 128.0;
 pub mod island;
-use island::Island;
+pub mod save;
+use island::tile::Tile;
+use island::heightmap::Pipeline;
+use island::{Island, IslandParams};
 use log::debug;
+use thiserror::Error;
 
-/// A `Chunk` is currently a placeholder struct to mark a certain world chunk as 'occupied'
-pub struct Chunk {}
+/// Minimum distance in tiles between a regenerated island and its neighbours
+pub const ISLAND_SPACING: f32 = 4.0;
+
+#[derive(Error, Debug)]
+pub enum RegenerateError {
+    #[error("There is no island {0}")]
+    NoIsland(usize),
+    #[error("Island {0} could not be generated from {1:?}")]
+    Generation(usize, IslandParams),
+    #[error("Regenerated island {0} would come closer than {ISLAND_SPACING} tiles to island {1}")]
+    Overlap(usize, usize),
+}
+
+/// A `Chunk` marks a certain world chunk as 'occupied'
+pub struct Chunk {
+    /// incremented whenever tiles inside this chunk change, so renderers know when to redraw it
+    pub revision: u64,
+}
 
 impl Chunk {
     pub fn new() -> Self {
-        Chunk {}
+        Chunk { revision: 0 }
     }
 }
 
 pub type ChunkIndex = euclid::default::Point2D<isize>;
 
+/// Index of the chunk that contains world position `pos`
+pub fn chunk_index(pos: WorldCoordinate) -> ChunkIndex {
+    ChunkIndex::new(
+        f32::floor(pos.x / CHUNK_SIZE) as isize,
+        f32::floor(pos.y / CHUNK_SIZE) as isize,
+    )
+}
+
 /// Game world. Is made out of islands.
+#[derive(Default)]
 pub struct World {
     /// islands in this world
     pub islands: Vec<Island>,
@@ -24,21 +54,44 @@ pub struct World {
     pub clipping_rect: WorldRect,
     /// chunks (indexed by upper left corner) mark world as 'generated' so areas that were visited once do not get re-generated
     pub chunks: std::collections::HashMap<ChunkIndex, Chunk>,
-    /// Screen center world position
-    pub screen_pos: WorldCoordinate,
+    /// recipe for new islands, the standard one if `None`
+    pub recipe: Option<Pipeline>,
 }
 
-impl Default for World {
-    fn default() -> Self {
-        World {
-            islands: Vec::new(),
-            clipping_rect: WorldRect::default(),
-            chunks: std::collections::HashMap::new(),
-            screen_pos: WorldCoordinate::new(0.0, 0.0),
+impl World {
+    /// Find the tile at world position `pos`. Returns the index of the owning island in `islands` and the tile index inside that island
+    pub fn tile_at(&self, pos: WorldCoordinate) -> Option<(usize, GridIndex)> {
+        for (i, island) in self.islands.iter().enumerate() {
+            if let Some(ind) = island.tile_index(pos) {
+                return Some((i, ind));
+            }
         }
+        None
     }
-}
-impl World {
+
+    /// Tile at world position `pos`
+    pub fn tile(&self, pos: WorldCoordinate) -> Option<&Tile> {
+        self.tile_at(pos).map(|(island, ind)| &self.islands[island].tiles[ind])
+    }
+
+    /// Tile at world position `pos`. Call `touch_chunks` after changing it
+    pub fn tile_mut(&mut self, pos: WorldCoordinate) -> Option<&mut Tile> {
+        self.tile_at(pos).map(|(island, ind)| &mut self.islands[island].tiles[ind])
+    }
+
+    /// Mark all generated chunks that intersect `area` as changed
+    pub fn touch_chunks(&mut self, area: WorldRect) {
+        let start_chunk = chunk_index(area.min());
+        let end_chunk = chunk_index(area.max());
+        for x in start_chunk.x..end_chunk.x + 1 {
+            for y in start_chunk.y..end_chunk.y + 1 {
+                if let Some(chunk) = self.chunks.get_mut(&ChunkIndex::new(x, y)) {
+                    chunk.revision += 1;
+                }
+            }
+        }
+    }
+
     /// Generate a new chunk with index `ind`
     pub fn gen_chunk(&mut self, ind: ChunkIndex) {
         // generating this chunk may cause a snowball effect
@@ -48,7 +101,7 @@ impl World {
         let die = rand::distributions::Bernoulli::new(0.5).unwrap();
         if die.sample(&mut rng) {
             // try to place island in middle of chunk
-            if let Some(mut island) = Island::new(chunk_pos + WorldVector::new(CHUNK_SIZE, CHUNK_SIZE)/2.0) {
+            if let Some(mut island) = self.generate_island(chunk_pos + WorldVector::new(CHUNK_SIZE, CHUNK_SIZE)/2.0, IslandParams::random()) {
                 let mut fits = false;
                 let mut intersects_none = true;
                 for index in self.chunks.keys() {
@@ -97,41 +150,9 @@ impl World {
                     fits = true;
                 }
                 if fits {
-                    // calculate min chunk position
-                    let chunk_min = ChunkIndex::new(
-                        f32::floor(island.clipping_rect.origin.x / CHUNK_SIZE) as isize,
-                        f32::floor(island.clipping_rect.origin.y / CHUNK_SIZE) as isize,
-                    );
-                    let chunk_max = ChunkIndex::new(
-                        f32::floor(island.clipping_rect.max_x() / CHUNK_SIZE) as isize,
-                        f32::floor(island.clipping_rect.max_y() / CHUNK_SIZE) as isize,
-                    );
-                    if chunk_min == chunk_max {
-                        if let std::collections::hash_map::Entry::Vacant(e) = self.chunks.entry(chunk_min) {
-                            debug!("Register chunk at {}, {}", chunk_min.x, chunk_min.y);
-                            e.insert(Chunk::new());
-                        }
-                        else {
-                            debug!("Chunk {} {} Already exists", chunk_min.x, chunk_min.y);
-                        }
-                    }
-                    else {
-                        for x in chunk_min.x..chunk_max.x {
-                            for y in chunk_min.y..chunk_max.y {
-                                let chunk_index = ChunkIndex::new(
-                                    x,
-                                    y,
-                                );
-                                if self.chunks.contains_key(&chunk_index) {
-                                    debug!("Chunk {} {} Already exists", x, y);
-                                    continue;
-                                }
-                                debug!("Register chunk at {}, {}", x, y);
-                                self.chunks.insert(chunk_index, Chunk::new());
-                            }
-                        }
-                    }
+                    self.register_chunks(island.clipping_rect);
                     debug!("Inserting island with clipping rect {:?} - {:?}", island.clipping_rect.origin, island.clipping_rect.size);
+                    self.touch_chunks(island.clipping_rect);
                     self.islands.push(island);
                 }
             }
@@ -140,8 +161,59 @@ impl World {
             debug!("Register chunk at {}, {}", ind.x, ind.y);
             Chunk::new()
         });
+        self.update_clipping_rect();
+    }
+
+    /// Replace island `index` by the island generated from `params` around the same centre
+    ///
+    /// Buildings on the old island are lost. The chunks the new island covers are registered as
+    /// generated, so no other island gets placed on top of it. Returns the area covered by the old
+    /// or the new island.
+    pub fn regenerate_island(&mut self, index: usize, params: IslandParams) -> Result<WorldRect, RegenerateError> {
+        let old_rect = self.islands.get(index).ok_or(RegenerateError::NoIsland(index))?.clipping_rect;
+        let island = self.generate_island(old_rect.center(), params).ok_or(RegenerateError::Generation(index, params))?;
+        for (i, other) in self.islands.iter().enumerate() {
+            if i != index && other.clipping_rect.inflate(ISLAND_SPACING, ISLAND_SPACING).intersects(&island.clipping_rect) {
+                return Err(RegenerateError::Overlap(index, i));
+            }
+        }
+        debug!("Regenerating island {} with clipping rect {:?} - {:?}", index, island.clipping_rect.origin, island.clipping_rect.size);
+        self.register_chunks(island.clipping_rect);
+        // cliffs of neighbouring tiles change with the coast
+        let area = old_rect.union(&island.clipping_rect);
+        self.touch_chunks(area.inflate(1.0, 1.0));
+        self.islands[index] = island;
+        self.update_clipping_rect();
+        Ok(area)
+    }
+
+    /// Island centred at `center` made with the world's recipe
+    fn generate_island(&self, center: WorldCoordinate, params: IslandParams) -> Option<Island> {
+        match &self.recipe {
+            Some(recipe) => Island::from_recipe(center, params, recipe),
+            None => Island::generate(center, params),
+        }
+    }
+
+    /// Register all chunks intersecting `area` that are not generated yet
+    fn register_chunks(&mut self, area: WorldRect) {
+        let chunk_min = chunk_index(area.min());
+        let chunk_max = chunk_index(area.max());
+        for x in chunk_min.x..=chunk_max.x {
+            for y in chunk_min.y..=chunk_max.y {
+                let chunk_index = ChunkIndex::new(x, y);
+                if self.chunks.contains_key(&chunk_index) {
+                    debug!("Chunk {} {} Already exists", x, y);
+                    continue;
+                }
+                debug!("Register chunk at {}, {}", x, y);
+                self.chunks.insert(chunk_index, Chunk::new());
+            }
+        }
+    }
 
-        // re-generate clipping rect of world
+    /// re-generate clipping rect of world from the generated chunks
+    fn update_clipping_rect(&mut self) {
         let mut min_pos = WorldCoordinate::new(f32::MAX, f32::MAX);
         let mut max_pos = WorldCoordinate::new(f32::MIN, f32::MIN);
         for index in self.chunks.keys() {
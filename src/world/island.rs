@@ -1,252 +1,111 @@
 use crate::glob::types::*;
-mod tile;
+use crate::grid::{Grid, GridIndex};
+pub mod heightmap;
+pub mod tile;
+use heightmap::{Heightmap, HeightmapOp, Pipeline};
 use tile::*;
 use rand::distributions::Distribution;
+use serde::{Deserialize, Serialize};
 #[derive(Debug)]
 /// Definition of an island
 pub struct Island {
     /// Minimum rectangle in world coordinates that includes all tiles
     pub clipping_rect: WorldRect,
     /// Tiles the island is made of
-    pub tiles: Vec<Vec<Tile>>,
+    pub tiles: Grid<Tile>,
+    /// Parameters the island was generated from
+    pub params: IslandParams,
+}
+/// exclusive upper bound of `IslandParams::randmap_exp`
+pub const MAX_RANDMAP_EXP: usize = 5;
+pub const MIN_RANDMAP_EXP: usize = 3;
+/// exclusive upper bound of `IslandParams::interpolation_scale`
+pub const MAX_INTERPOLATION_SCALE: usize = 12;
+pub const MIN_INTERPOLATION_SCALE: usize = 8;
+
+/// Parameters an island is generated from. The same parameters and recipe give the same island
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IslandParams {
+    pub seed: u64,
+    // the sizes below are only used by the standard recipe, see `Pipeline::standard`
+    /// the random map has 2^randmap_exp + 3 cells per side
+    pub randmap_exp: usize,
+    /// tiles per random map cell
+    pub interpolation_scale: usize,
 }
-const MAX_RANDMAP_EXP: usize = 5;
-const MIN_RANDMAP_EXP: usize = 3;
-const MAX_INTERPOLATION_SCALE: usize = 12;
-const MIN_INTERPOLATION_SCALE: usize = 8;
-const GAUSS_WINDOW_SIZE: usize = 7;
-const GAUSS_WINDOW: [[f32; GAUSS_WINDOW_SIZE]; GAUSS_WINDOW_SIZE] = [
-    [0.00000067, 0.00002292, 0.00019117, 0.00038771, 0.00019117, 0.00002292, 0.00000067],
-    [0.00002292, 0.00078633, 0.00655965, 0.01330373, 0.00655965, 0.00078633, 0.00002292],
-    [0.00019117, 0.00655965, 0.05472157, 0.11098164, 0.05472157, 0.00655965, 0.00019117],
-    [0.00038771, 0.01330373, 0.11098164, 0.22508352, 0.11098164, 0.01330373, 0.00038771],
-    [0.00019117, 0.00655965, 0.05472157, 0.11098164, 0.05472157, 0.00655965, 0.00019117],
-    [0.00002292, 0.00078633, 0.00655965, 0.01330373, 0.00655965, 0.00078633, 0.00002292],
-    [0.00000067, 0.00002292, 0.00019117, 0.00038771, 0.00019117, 0.00002292, 0.00000067]];
-impl Island {
-    /// try to create an island at `origin`
-    pub fn new(origin: WorldCoordinate) -> Option<Self> {
-        // 1. generate random map with diamond square algorithm
-        // note: array must be quadratic with edge len 2^n + 1
-        // we add water padding, so 2^n + 3
-        let mut rng = rand::thread_rng();
-        let die = rand::distributions::Uniform::new(MIN_RANDMAP_EXP, MAX_RANDMAP_EXP);
-        let exp = die.sample(&mut rng) as u32;
-        let randmap_size = (u32::pow(2, exp) + 3) as usize;
-        log::trace!("Randmap size {}", randmap_size);
-        let mut randmap: Vec<Vec<f32>> = vec![vec![0.0; randmap_size]; randmap_size];
-
-        // define area in which to apply diamond-square algorithm
-        let area = euclid::default::Rect::<usize>::new(
-            euclid::default::Point2D::new(1, 1),
-            euclid::default::Size2D::new(randmap.len()-2, randmap[0].len()-2));
-
-        Island::diamond_square_gen(
-            &mut randmap,
-            area,
-            0
-        );
 
-        // set outer border to -1.0
-        for x in 0..randmap_size {
-            for y in 0..randmap_size {
-                if x > 0 && (x < randmap_size - 1) && y > 0 && y < (randmap_size - 1) {
-                    continue;
-                }
-                randmap[x][y] = -0.1;
-            }
+impl IslandParams {
+    pub fn random() -> Self {
+        let mut rng = rand::thread_rng();
+        IslandParams {
+            seed: rand::Rng::gen(&mut rng),
+            randmap_exp: rand::distributions::Uniform::new(MIN_RANDMAP_EXP, MAX_RANDMAP_EXP).sample(&mut rng),
+            interpolation_scale: rand::distributions::Uniform::new(MIN_INTERPOLATION_SCALE, MAX_INTERPOLATION_SCALE).sample(&mut rng),
         }
+    }
 
-        // 2. Generate heightmap using bilinear interpolation of randmap
-        let die = rand::distributions::Uniform::new(MIN_INTERPOLATION_SCALE, MAX_INTERPOLATION_SCALE);
-        let interpolation_scale = die.sample(&mut rng) as usize;
-        let heightmap = Island::interpolate(randmap, interpolation_scale);
-
-        // 3. smooth it
-        // let mut smooth_heightmap = Island::average_smooth(heightmap, 3);
-        let smooth_heightmap = Island::gauss_smooth(heightmap);
-
-        let cut_heightmap = Island::cut_map(smooth_heightmap);
-        if cut_heightmap.is_empty() {
-            return None;
+    /// the same parameters with a new random seed
+    pub fn reseeded(self) -> Self {
+        IslandParams {
+            seed: rand::Rng::gen(&mut rand::thread_rng()),
+            ..self
         }
-        if cut_heightmap[0].is_empty() {
+    }
+}
+impl Island {
+    /// try to create the island described by `params` centred at `center` with the standard recipe
+    pub fn generate(center: WorldCoordinate, params: IslandParams) -> Option<Self> {
+        Island::from_recipe(center, params, &Pipeline::standard(&params))
+    }
+
+    /// try to create an island centred at `center` with `recipe`, which draws random numbers from `params.seed`
+    pub fn from_recipe(center: WorldCoordinate, params: IslandParams, recipe: &Pipeline) -> Option<Self> {
+        let heightmap = recipe.run(params.seed, &mut |_, _| {});
+        if heightmap.is_empty() {
             return None;
         }
-        // calculate clipping rect
-        let clipping_rect = WorldRect::new(
-            origin + WorldVector::new(-(cut_heightmap.len() as f32)/2.0, -(cut_heightmap.len() as f32)/2.0),
-            WorldVector::new(cut_heightmap[0].len() as f32, cut_heightmap[0].len() as f32).to_size(),
-        );
+        // calculate clipping rect, aligned to the world grid so tiles lie on whole coordinates
+        let size = WorldVector::new(heightmap.width() as f32, heightmap.height() as f32);
+        let clipping_rect = WorldRect::new((center - size / 2.0).floor(), size.to_size());
 
-        let mut tiles: Vec<Vec<Tile>> = Vec::new();
-        for x in 0..cut_heightmap.len() as usize {
-            let mut new_col: Vec<Tile> = Vec::new();
-            for y in 0..cut_heightmap[x].len() as usize {
-                let mut tile = Tile::new(clipping_rect.origin + WorldVector::new(x as f32, y as f32));
-                tile.height = cut_heightmap[x][y];
-                new_col.push(tile);
-            }
-            tiles.push(new_col);
-        }
         log::info!("Island created");
         Some(Island {
             clipping_rect,
-            tiles,
+            tiles: heightmap.map(|height| Tile::new(*height)),
+            params,
         })
     }
 
-    /// create minimal rectangle
-    fn cut_map(map: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        // -- find minimal coordinates, find maximum coordinates
-        let mut min_col = map.len()-1;
-        let mut max_col = 0;
-        let mut min_row = map[0].len()-1;
-        let mut max_row = 0;
-        for x in 0..map.len() {
-            for y in 0..map[x].len() {
-                if map[x][y] > 0.0 {
-                    if min_col > x {min_col = x;}
-                    if max_col < x {max_col = x;}
-                    if min_row > y {min_row = y;}
-                    if max_row < y {max_row = y;}
-                }
-            }
-        }
-        let mut cut_heightmap: Vec<Vec<f32>> = Vec::new();
-        for x in min_col..(max_col+1) {
-            let mut new_col: Vec<f32> = Vec::new();
-            for y in min_row..(max_row+1) {
-                new_col.push(map[x][y]);
-            }
-            cut_heightmap.push(new_col);
-        }
-        cut_heightmap
+    /// Heightmaps after every operation of `recipe` for the island described by `params`, in order
+    pub fn generation_stages(params: IslandParams, recipe: &Pipeline) -> Vec<(HeightmapOp, Heightmap)> {
+        let mut stages = Vec::new();
+        recipe.run(params.seed, &mut |op, map| stages.push((op.clone(), map.clone())));
+        stages
     }
 
-    /// bilinear interpolation of randmap to array of size randmap.len() * interpolation_scale
-    /// formula from wikipedia
-    fn interpolate(randmap: Vec<Vec<f32>>, interpolation_scale: usize) -> Vec<Vec<f32>> {
-        let heightmap_size = (randmap.len()-1) * interpolation_scale;
-        let mut ret = vec![vec![0.0; heightmap_size]; heightmap_size];
-        for x in 0..ret.len() {
-            for y in 0..ret[x].len() {
-                // according indices in randmap
-                let rand_x = x as f32 / interpolation_scale as f32;
-                let rand_y = y as f32 / interpolation_scale as f32;
-
-                // upper left corner in randmap
-                let x1 = rand_x as usize;
-                let y1 = rand_y as usize;
-                // lower right corner in randmap
-                let x2 = rand_x as usize + 1;
-                let y2 = rand_y as usize + 1;
-
-                // not-normalized interpolation
-                let inter: f32 =
-                    randmap[x1][y1] * (1.0 - f32::fract(rand_x)) * (1.0 - f32::fract(rand_y))
-                    + randmap[x2][y1] * f32::fract(rand_x) * (1.0 - f32::fract(rand_y))
-                    + randmap[x1][y2] * (1.0 - f32::fract(rand_x)) * f32::fract(rand_y)
-                    + randmap[x2][y2] * f32::fract(rand_x) * f32::fract(rand_y);
-
-                ret[x][y] = inter / 4.0;
-            }
-        }
-        ret
-    }
-    /// 2D Gauss smooth
-    fn gauss_smooth(map: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        let mut ret: Vec<Vec<f32>> = Vec::new();
-        for x in 0..map.len() {
-            let mut new_col: Vec<f32> = Vec::new();
-            for y in 0..map[x].len() {
-                let mut acc: f32 = 0.0;
-                let x_signed = x as isize;
-                let y_signed = y as isize;
-                for dx in -(GAUSS_WINDOW_SIZE as isize)/2..((GAUSS_WINDOW_SIZE/2+1) as isize) {
-                    for dy in -(GAUSS_WINDOW_SIZE as isize)/2..((GAUSS_WINDOW_SIZE/2+1) as isize) {
-                        let xx = x_signed+dx;
-                        let yy = y_signed+dy;
-                        if (yy >= 0) && ((yy as usize) < map[x].len())
-                            && (xx >= 0) && ((xx as usize) < map.len()) {
-                                acc += 2.0 *map[xx as usize][yy as usize] * GAUSS_WINDOW[(dx + (GAUSS_WINDOW_SIZE as isize)/2) as usize][(dy + (GAUSS_WINDOW_SIZE as isize)/2) as usize];
-                            }
-                        else {
-                            // acc -= 0.5 * gauss_filter[(dx + GAUSS_WINDOW_SIZE/2) as usize][(dy + GAUSS_WINDOW_SIZE/2) as usize];
-                        }
-                    }
-                }
-                let avg = acc / GAUSS_WINDOW_SIZE as f32;
-                new_col.push(avg);
-            }
-            ret.push(new_col);
-        }
-        ret
+    /// world position of the upper left corner of tile `x`, `y`
+    pub fn tile_pos(&self, x: usize, y: usize) -> WorldCoordinate {
+        self.clipping_rect.origin + WorldVector::new(x as f32, y as f32)
     }
 
-    const HEIGHT_RAND_MAX: f32 = 0.1;
-    const RAND_MAG: f32 = 0.1;
-    /// Generate terrain with diamond-square algorithm
-    fn diamond_square_gen(map: &mut Vec<Vec<f32>>, corners: euclid::default::Rect<usize>, it: usize) {
-        if corners.width() < 2 || corners.height() < 2 {
-            return;
+    /// index into `tiles` of the tile at world position `pos`
+    pub fn tile_index(&self, pos: WorldCoordinate) -> Option<GridIndex> {
+        let rel = pos - self.clipping_rect.origin;
+        if rel.x < 0.0 || rel.y < 0.0 {
+            return None;
         }
-        let local_center_coord = corners.center();
-        let mut rng = rand::thread_rng();
-        let mag = f32::powf(2.0, -Island::RAND_MAG * it as f32);
-        let die = rand::distributions::Uniform::new(-Island::HEIGHT_RAND_MAX * mag, Island::HEIGHT_RAND_MAX * mag);
-
-        // "diamond" step: add average of corners plus random to center of rect
-        let upper_left = map[corners.min_x()][corners.min_y()];
-        let lower_left = map[corners.min_x()][corners.max_y()];
-        let upper_right = map[corners.max_x()][corners.min_y()];
-        let lower_right = map[corners.max_y()][corners.max_y()];
-        let center = (upper_left + upper_right + lower_left + lower_right) / 4.0 + die.sample(&mut rng);
-        map[local_center_coord.x][local_center_coord.y] += center;
-
-        // "square" step: add average of corners plus random to borders of rect
-        let center_weight = 2.0;
-        let avg_divider = 4.0;
-        let west_average = (upper_left + lower_left + center_weight * center) / avg_divider + die.sample(&mut rng);
-        let north_average = (upper_left + upper_right + center_weight * center) / avg_divider + die.sample(&mut rng);
-        let east_average = (upper_right + lower_right + center_weight * center) / avg_divider + die.sample(&mut rng);
-        let south_average = (lower_right + lower_left + center_weight * center) / avg_divider + die.sample(&mut rng);
-
-        let west_coord = corners.origin + euclid::default::Vector2D::new(0, corners.height() / 2);
-        let north_coord = corners.origin + euclid::default::Vector2D::new(corners.width() / 2, 0);
-        let east_coord = west_coord + euclid::default::Vector2D::new(corners.width(), 0);
-        let south_coord = north_coord + euclid::default::Vector2D::new(0, corners.height());
-
-        map[west_coord.x][west_coord.y]   += west_average;
-        map[north_coord.x][north_coord.y] += north_average;
-        map[east_coord.x][east_coord.y]   += east_average;
-        map[south_coord.x][south_coord.y] += south_average;
-
-        // recurse sub squares
-        let next_squares = [
-            euclid::default::Rect::from_points(vec![corners.origin, local_center_coord].into_iter()),
-            euclid::default::Rect::from_points(vec![west_coord, south_coord].into_iter()),
-            euclid::default::Rect::from_points(vec![north_coord, east_coord].into_iter()),
-            euclid::default::Rect::from_points(vec![local_center_coord, euclid::default::Point2D::new(corners.max_x(), corners.max_y())].into_iter())
-        ];
-        for square in next_squares {
-            Island::diamond_square_gen(map, square, it + 1);
+        let (x, y) = (rel.x as usize, rel.y as usize);
+        if !self.tiles.contains(x, y) {
+            return None;
         }
+        Some(GridIndex::new(x, y))
     }
 
-    /// shift entire island by `offset`
+    /// shift entire island by `offset`. Tile positions follow from the clipping rect, so only it moves
     ///
     /// if a new island does not fit into the world immediately (because it intersects other islands)
     /// it is virtually shifted around to find a suitable place
     pub fn shift(&mut self, offset: WorldCoordinate) {
-        let shift_matrix = euclid::Translation2D::<f32, WorldSpace, WorldSpace>::new(
-            offset.x, offset.y,
-        );
-        for col in &mut self.tiles {
-            for tile in col {
-                tile.pos = shift_matrix.transform_point(tile.pos);
-            }
-        }
-        self.clipping_rect = shift_matrix.transform_rect(&self.clipping_rect);
+        self.clipping_rect = self.clipping_rect.translate(offset.to_vector());
     }
 }
@@ -0,0 +1,442 @@
+//! Composable heightmap operations
+//!
+//! An island recipe is a `Pipeline` of `HeightmapOp`s applied one after the other, starting from an
+//! empty heightmap. Noise creates a new map, the other operations transform the previous one.
+//! Pipelines are built in code or read from a YAML file like
+//!
+//! ```yaml
+//! ops:
+//!   - op: Noise
+//!     exp: 4
+//!   - op: Interpolate
+//!     scale: 10
+//!   - op: Blur
+//!   - op: Terrace
+//!     step: 0.5
+//!   - op: Crop
+//! ```
+//!
+//! Operations that compute every cell on its own split the rows among threads. The noise stays on
+//! one thread, so a seed always gives the same island.
+use super::IslandParams;
+use crate::grid::{Grid, GridIndex, GridRect};
+use rand::distributions::Distribution;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub type Heightmap = Grid<f32>;
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("Failed to read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Failed to parse {0}: {1}")]
+    Parse(String, serde_yaml::Error),
+    #[error("Invalid operation {0} ({1}): {2}")]
+    Invalid(usize, &'static str, String),
+}
+
+/// Largest `HeightmapOp::Noise` exponent, bounds the size of the noise
+const MAX_NOISE_EXP: usize = 8;
+/// Largest `HeightmapOp::Interpolate` scale, bounds the size of the island
+const MAX_SCALE: usize = 16;
+const MAX_EROSION_ITERATIONS: usize = 1000;
+
+/// Height of the water border around noise
+const NOISE_BORDER: f32 = -0.1;
+const HEIGHT_RAND_MAX: f32 = 0.1;
+const RAND_MAG: f32 = 0.1;
+
+/// Gauss kernel. The 7x7 filter is its outer product with itself, so it is applied along rows and
+/// columns one after the other
+const GAUSS_KERNEL: [f32; 7] = [0.00081721, 0.02804152, 0.23392643, 0.47442968, 0.23392643, 0.02804152, 0.00081721];
+/// Factor applied on top of the normalized kernel
+const GAUSS_GAIN: f32 = 2.0 / GAUSS_KERNEL.len() as f32;
+
+/// One step of a `Pipeline`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum HeightmapOp {
+    /// replace the map by diamond square noise of 2^exp + 1 cells per side in a border of water
+    Noise { exp: usize },
+    /// bilinear interpolation with `scale` cells per input cell
+    Interpolate { scale: usize },
+    /// 7x7 Gauss filter
+    Blur,
+    /// thermal erosion: slopes steeper than `talus` shed `rate` of the excess to lower neighbours
+    Erosion { iterations: usize, talus: f32, rate: f32 },
+    /// lower heights by `strength` times the squared distance from the centre, 1 at the edge midpoints
+    Falloff { strength: f32 },
+    /// round heights down to multiples of `step`
+    Terrace { step: f32 },
+    Clamp { min: f32, max: f32 },
+    /// scale heights linearly to the range from `min` to `max`
+    Normalize { min: f32, max: f32 },
+    /// smallest rectangle containing all land
+    Crop,
+}
+
+impl HeightmapOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeightmapOp::Noise { .. } => "Noise",
+            HeightmapOp::Interpolate { .. } => "Interpolate",
+            HeightmapOp::Blur => "Blur",
+            HeightmapOp::Erosion { .. } => "Erosion",
+            HeightmapOp::Falloff { .. } => "Falloff",
+            HeightmapOp::Terrace { .. } => "Terrace",
+            HeightmapOp::Clamp { .. } => "Clamp",
+            HeightmapOp::Normalize { .. } => "Normalize",
+            HeightmapOp::Crop => "Crop",
+        }
+    }
+
+    /// Check the parameters. The error tells what is wrong
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            HeightmapOp::Noise { exp } if !(1..=MAX_NOISE_EXP).contains(&exp) => {
+                Err(format!("exp must be between 1 and {}", MAX_NOISE_EXP))
+            }
+            HeightmapOp::Interpolate { scale } if !(1..=MAX_SCALE).contains(&scale) => {
+                Err(format!("scale must be between 1 and {}", MAX_SCALE))
+            }
+            HeightmapOp::Erosion { iterations, .. } if iterations > MAX_EROSION_ITERATIONS => {
+                Err(format!("iterations must be at most {}", MAX_EROSION_ITERATIONS))
+            }
+            HeightmapOp::Erosion { talus, rate, .. } if !(talus >= 0.0 && (0.0..=1.0).contains(&rate)) => {
+                Err("talus must not be negative and rate must be between 0 and 1".to_string())
+            }
+            HeightmapOp::Falloff { strength } if !strength.is_finite() => Err("strength must be finite".to_string()),
+            HeightmapOp::Terrace { step } if step.is_nan() || step <= 0.0 => Err("step must be positive".to_string()),
+            HeightmapOp::Clamp { min, max } | HeightmapOp::Normalize { min, max } if min.is_nan() || max.is_nan() || min > max => {
+                Err("min must not be greater than max".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply the operation to `map`. Only noise draws from `rng`
+    pub fn apply(&self, map: Heightmap, rng: &mut rand::rngs::StdRng) -> Heightmap {
+        if let HeightmapOp::Noise { exp } = *self {
+            return noise(exp, rng);
+        }
+        if map.is_empty() {
+            return map;
+        }
+        match *self {
+            HeightmapOp::Noise { .. } => unreachable!(),
+            HeightmapOp::Interpolate { scale } => interpolate(&map, scale),
+            HeightmapOp::Blur => gauss_smooth(&map),
+            HeightmapOp::Erosion { iterations, talus, rate } => erode(map, iterations, talus, rate),
+            HeightmapOp::Falloff { strength } => falloff(map, strength),
+            HeightmapOp::Terrace { step } if step > 0.0 => map_heights(map, |h| (h / step).floor() * step),
+            HeightmapOp::Terrace { .. } => map,
+            HeightmapOp::Clamp { min, max } => map_heights(map, |h| h.clamp(min, max)),
+            HeightmapOp::Normalize { min, max } => normalize(map, min, max),
+            HeightmapOp::Crop => crop(&map),
+        }
+    }
+}
+
+/// Island recipe
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub ops: Vec<HeightmapOp>,
+}
+
+impl Pipeline {
+    /// The recipe of islands generated from `params`: noise, interpolation, blur and crop
+    pub fn standard(params: &IslandParams) -> Self {
+        Pipeline {
+            ops: vec![
+                HeightmapOp::Noise { exp: params.randmap_exp },
+                HeightmapOp::Interpolate { scale: params.interpolation_scale },
+                HeightmapOp::Blur,
+                HeightmapOp::Crop,
+            ],
+        }
+    }
+
+    /// Read a pipeline from the YAML file at `path` and validate it
+    pub fn load(path: &str) -> Result<Self, PipelineError> {
+        let content = std::fs::read_to_string(path).map_err(|e| PipelineError::Read(path.to_string(), e))?;
+        let pipeline: Pipeline = serde_yaml::from_str(&content).map_err(|e| PipelineError::Parse(path.to_string(), e))?;
+        pipeline.validate()?;
+        Ok(pipeline)
+    }
+
+    /// Check the parameters of all operations
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        for (i, op) in self.ops.iter().enumerate() {
+            op.validate().map_err(|reason| PipelineError::Invalid(i, op.name(), reason))?;
+        }
+        Ok(())
+    }
+
+    /// Apply all operations with random numbers drawn from `seed`. `record` is called with the result of every operation
+    pub fn run(&self, seed: u64, record: &mut dyn FnMut(&HeightmapOp, &Heightmap)) -> Heightmap {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut map = Heightmap::default();
+        for op in &self.ops {
+            map = op.apply(map, &mut rng);
+            record(op, &map);
+        }
+        map
+    }
+}
+
+fn map_heights(mut map: Heightmap, f: impl Fn(f32) -> f32) -> Heightmap {
+    for height in map.iter_mut() {
+        *height = f(*height);
+    }
+    map
+}
+
+/// Diamond square noise on 2^exp + 1 cells, padded with water to 2^exp + 3
+fn noise(exp: usize, rng: &mut rand::rngs::StdRng) -> Heightmap {
+    let randmap_size = (u32::pow(2, exp as u32) + 3) as usize;
+    log::trace!("Randmap size {}", randmap_size);
+    let mut randmap = Heightmap::new(randmap_size, randmap_size, 0.0);
+
+    // define area in which to apply diamond-square algorithm
+    let area = GridRect::new(
+        GridIndex::new(1, 1),
+        euclid::default::Size2D::new(randmap_size - 2, randmap_size - 2));
+
+    diamond_square_gen(&mut randmap, area, 0, rng);
+
+    // set outer border to water
+    for (y, row) in randmap.rows_mut().enumerate() {
+        for (x, height) in row.iter_mut().enumerate() {
+            if x > 0 && (x < randmap_size - 1) && y > 0 && y < (randmap_size - 1) {
+                continue;
+            }
+            *height = NOISE_BORDER;
+        }
+    }
+    randmap
+}
+
+/// Generate terrain with diamond-square algorithm
+fn diamond_square_gen(map: &mut Heightmap, corners: GridRect, it: usize, rng: &mut rand::rngs::StdRng) {
+    if corners.width() < 2 || corners.height() < 2 {
+        return;
+    }
+    let local_center_coord = corners.center();
+    let mag = f32::powf(2.0, -RAND_MAG * it as f32);
+    let die = rand::distributions::Uniform::new(-HEIGHT_RAND_MAX * mag, HEIGHT_RAND_MAX * mag);
+
+    // "diamond" step: add average of corners plus random to center of rect
+    let upper_left = map[(corners.min_x(), corners.min_y())];
+    let lower_left = map[(corners.min_x(), corners.max_y())];
+    let upper_right = map[(corners.max_x(), corners.min_y())];
+    let lower_right = map[(corners.max_y(), corners.max_y())];
+    let center = (upper_left + upper_right + lower_left + lower_right) / 4.0 + die.sample(rng);
+    map[local_center_coord] += center;
+
+    // "square" step: add average of corners plus random to borders of rect
+    let center_weight = 2.0;
+    let avg_divider = 4.0;
+    let west_average = (upper_left + lower_left + center_weight * center) / avg_divider + die.sample(rng);
+    let north_average = (upper_left + upper_right + center_weight * center) / avg_divider + die.sample(rng);
+    let east_average = (upper_right + lower_right + center_weight * center) / avg_divider + die.sample(rng);
+    let south_average = (lower_right + lower_left + center_weight * center) / avg_divider + die.sample(rng);
+
+    let west_coord = corners.origin + euclid::default::Vector2D::new(0, corners.height() / 2);
+    let north_coord = corners.origin + euclid::default::Vector2D::new(corners.width() / 2, 0);
+    let east_coord = west_coord + euclid::default::Vector2D::new(corners.width(), 0);
+    let south_coord = north_coord + euclid::default::Vector2D::new(0, corners.height());
+
+    map[west_coord]  += west_average;
+    map[north_coord] += north_average;
+    map[east_coord]  += east_average;
+    map[south_coord] += south_average;
+
+    // recurse sub squares
+    let next_squares = [
+        GridRect::from_points([corners.origin, local_center_coord]),
+        GridRect::from_points([west_coord, south_coord]),
+        GridRect::from_points([north_coord, east_coord]),
+        GridRect::from_points([local_center_coord, GridIndex::new(corners.max_x(), corners.max_y())]),
+    ];
+    for square in next_squares {
+        diamond_square_gen(map, square, it + 1, rng);
+    }
+}
+
+/// bilinear interpolation of `map` to (size - 1) * `scale` cells
+/// formula from wikipedia
+fn interpolate(map: &Heightmap, scale: usize) -> Heightmap {
+    let width = (map.width() - 1) * scale;
+    let height = (map.height() - 1) * scale;
+    Heightmap::par_from_fn(width, height, |x, y| {
+        // according indices in map
+        let rand_x = x as f32 / scale as f32;
+        let rand_y = y as f32 / scale as f32;
+
+        // upper left corner in map
+        let x1 = rand_x as usize;
+        let y1 = rand_y as usize;
+        // lower right corner in map
+        let x2 = rand_x as usize + 1;
+        let y2 = rand_y as usize + 1;
+
+        // not-normalized interpolation
+        let inter: f32 =
+            map[(x1, y1)] * (1.0 - f32::fract(rand_x)) * (1.0 - f32::fract(rand_y))
+            + map[(x2, y1)] * f32::fract(rand_x) * (1.0 - f32::fract(rand_y))
+            + map[(x1, y2)] * (1.0 - f32::fract(rand_x)) * f32::fract(rand_y)
+            + map[(x2, y2)] * f32::fract(rand_x) * f32::fract(rand_y);
+
+        inter / 4.0
+    })
+}
+
+/// 2D Gauss smooth as two 1D passes. Cells outside of the map count as 0
+fn gauss_smooth(map: &Heightmap) -> Heightmap {
+    let rows = Heightmap::par_from_fn(map.width(), map.height(), |x, y| convolve(map.row(y).iter(), x));
+    Heightmap::par_from_fn(map.width(), map.height(), |x, y| convolve(rows.column(x), y) * GAUSS_GAIN)
+}
+
+/// `GAUSS_KERNEL` centred on cell `i` of `line`
+fn convolve<'a>(line: impl Iterator<Item = &'a f32>, i: usize) -> f32 {
+    let radius = GAUSS_KERNEL.len() / 2;
+    let first = i.saturating_sub(radius);
+    // the kernel is cut where it reaches over the start or end of the line
+    line.skip(first).zip(&GAUSS_KERNEL[first + radius - i..]).map(|(height, weight)| height * weight).sum()
+}
+
+/// Thermal erosion. Every cell gathers what flows in from and out to its neighbours based on the
+/// heights before the iteration, so cells are independent of each other
+fn erode(mut map: Heightmap, iterations: usize, talus: f32, rate: f32) -> Heightmap {
+    // a quarter per neighbour, so a cell never sheds more than its excess
+    let flow = |from: f32, to: f32| (from - to - talus).max(0.0) * rate * 0.25;
+    for _ in 0..iterations {
+        map = Heightmap::par_from_fn(map.width(), map.height(), |x, y| {
+            let height = map[(x, y)];
+            let mut new_height = height;
+            // the four neighbours sharing an edge
+            for (index, &neighbour) in map.window(x, y, 1).indexed_iter() {
+                if index.x.abs_diff(x) + index.y.abs_diff(y) == 1 {
+                    new_height += flow(neighbour, height) - flow(height, neighbour);
+                }
+            }
+            new_height
+        });
+    }
+    map
+}
+
+fn falloff(map: Heightmap, strength: f32) -> Heightmap {
+    let half_width = map.width() as f32 / 2.0;
+    let half_height = map.height() as f32 / 2.0;
+    Heightmap::par_from_fn(map.width(), map.height(), |x, y| {
+        let dx = (x as f32 + 0.5 - half_width) / half_width;
+        let dy = (y as f32 + 0.5 - half_height) / half_height;
+        let height = map[(x, y)];
+        height - strength * (dx * dx + dy * dy)
+    })
+}
+
+fn normalize(map: Heightmap, min: f32, max: f32) -> Heightmap {
+    let lowest = map.iter().copied().fold(f32::MAX, f32::min);
+    let highest = map.iter().copied().fold(f32::MIN, f32::max);
+    let range = highest - lowest;
+    if range <= 0.0 {
+        return map_heights(map, |_| min);
+    }
+    map_heights(map, |h| min + (h - lowest) / range * (max - min))
+}
+
+/// create minimal rectangle around all heights above 0
+fn crop(map: &Heightmap) -> Heightmap {
+    let is_land = |height: &f32| *height > 0.0;
+    let top = match map.rows().position(|row| row.iter().any(is_land)) {
+        Some(top) => top,
+        None => return Heightmap::default(),
+    };
+    // there is land, so the other borders exist
+    let bottom = map.rows().rposition(|row| row.iter().any(is_land)).unwrap();
+    let left = map.columns().position(|mut column| column.any(is_land)).unwrap();
+    let right = map.columns().rposition(|mut column| column.any(is_land)).unwrap();
+    map.view(GridRect::from_points([GridIndex::new(left, top), GridIndex::new(right + 1, bottom + 1)])).to_grid()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2D filter `gauss_smooth` replaced
+    const GAUSS_WINDOW: [[f32; 7]; 7] = [
+        [0.00000067, 0.00002292, 0.00019117, 0.00038771, 0.00019117, 0.00002292, 0.00000067],
+        [0.00002292, 0.00078633, 0.00655965, 0.01330373, 0.00655965, 0.00078633, 0.00002292],
+        [0.00019117, 0.00655965, 0.05472157, 0.11098164, 0.05472157, 0.00655965, 0.00019117],
+        [0.00038771, 0.01330373, 0.11098164, 0.22508352, 0.11098164, 0.01330373, 0.00038771],
+        [0.00019117, 0.00655965, 0.05472157, 0.11098164, 0.05472157, 0.00655965, 0.00019117],
+        [0.00002292, 0.00078633, 0.00655965, 0.01330373, 0.00655965, 0.00078633, 0.00002292],
+        [0.00000067, 0.00002292, 0.00019117, 0.00038771, 0.00019117, 0.00002292, 0.00000067]];
+
+    /// Interpolated noise, the input of the blur in the standard recipe
+    fn seeded_map(seed: u64) -> Heightmap {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        interpolate(&noise(4, &mut rng), 4)
+    }
+
+    #[test]
+    fn separable_gauss_matches_window() {
+        let map = seeded_map(7);
+        let smooth = gauss_smooth(&map);
+        for (index, height) in smooth.indexed_iter() {
+            let (x, y) = (index.x as isize, index.y as isize);
+            let mut expected = 0.0;
+            for (i, row) in GAUSS_WINDOW.iter().enumerate() {
+                for (j, weight) in row.iter().enumerate() {
+                    if let Some(cell) = map.at(x + i as isize - 3, y + j as isize - 3) {
+                        expected += 2.0 * cell * weight;
+                    }
+                }
+            }
+            expected /= 7.0;
+            assert!((height - expected).abs() < 1e-5, "{} instead of {} at {:?}", height, expected, index);
+        }
+    }
+
+    #[test]
+    fn crop_keeps_all_land() {
+        let mut map = Heightmap::new(6, 5, -0.1);
+        map[(2, 1)] = 0.5;
+        map[(4, 3)] = 0.2;
+        let cropped = crop(&map);
+        assert_eq!((cropped.width(), cropped.height()), (3, 3));
+        assert_eq!(cropped[(0, 0)], 0.5);
+        assert_eq!(cropped[(2, 2)], 0.2);
+        assert!(crop(&Heightmap::new(3, 3, -0.1)).is_empty());
+    }
+
+    #[test]
+    fn pipeline_is_deterministic() {
+        let recipe = Pipeline {
+            ops: vec![
+                HeightmapOp::Noise { exp: 4 },
+                HeightmapOp::Interpolate { scale: 4 },
+                HeightmapOp::Blur,
+                HeightmapOp::Erosion { iterations: 5, talus: 0.01, rate: 0.5 },
+                HeightmapOp::Falloff { strength: 0.2 },
+            ],
+        };
+        let first = recipe.run(42, &mut |_, _| {});
+        assert_eq!(first.width(), 72);
+        assert_eq!(recipe.run(42, &mut |_, _| {}), first);
+        assert_ne!(recipe.run(43, &mut |_, _| {}), first);
+    }
+
+    #[test]
+    fn erosion_keeps_material() {
+        let map = seeded_map(3);
+        let eroded = erode(map.clone(), 10, 0.0, 0.5);
+        let before: f32 = map.iter().sum();
+        let after: f32 = eroded.iter().sum();
+        assert!((before - after).abs() < 1e-3, "{} before and {} after", before, after);
+    }
+}
@@ -1,15 +1,146 @@
-use crate::glob::types::*;
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+/// Height difference between two elevation levels
+pub const LEVEL_HEIGHT: f32 = 0.5;
+/// Highest height the terrain is drawn and picked at
+pub const MAX_HEIGHT: f32 = 4.0;
+/// Deepest water. Depth is not drawn, so edits stop here
+pub const MIN_HEIGHT: f32 = -LEVEL_HEIGHT;
+
+/// Height resolution. Heights are stored as multiples of it, from -32 to 32
+pub const HEIGHT_STEP: f32 = 1.0 / 1024.0;
+/// Bits of `Tile::flags` holding the resource code, see `Resource::code`
+const RESOURCE_MASK: u8 = 0b0000_0011;
+/// Bits of `Tile::flags` holding the building code, see `Building::code`
+const BUILDING_MASK: u8 = 0b0000_1100;
+const BUILDING_SHIFT: u32 = 2;
+
+/// Terrain of one grid cell. Its position follows from its index in `Island::tiles`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Tile {
-    pub pos: WorldCoordinate,
-    pub height: f32,
+    /// height in multiples of `HEIGHT_STEP`
+    height: i16,
+    /// resource and building codes, 0 for none
+    flags: u8,
 }
 
 impl Tile {
-    pub fn new(pos: WorldCoordinate) -> Self {
-        Tile {
-            pos,
-            height: 0.0,
+    /// Tile at `height` without resource and building
+    pub fn new(height: f32) -> Self {
+        let mut tile = Tile::default();
+        tile.set_height(height);
+        tile
+    }
+    pub fn height(&self) -> f32 {
+        self.height as f32 * HEIGHT_STEP
+    }
+    /// Set the height, rounded to `HEIGHT_STEP` and clamped to the storable range
+    pub fn set_height(&mut self, height: f32) {
+        // float to int casts saturate
+        self.height = (height / HEIGHT_STEP).round() as i16;
+    }
+    /// natural resource on this tile
+    pub fn resource(&self) -> Option<Resource> {
+        Resource::from_code(self.flags & RESOURCE_MASK)
+    }
+    pub fn set_resource(&mut self, resource: Option<Resource>) {
+        self.flags = (self.flags & !RESOURCE_MASK) | resource.map_or(0, Resource::code);
+    }
+    /// building placed on this tile
+    pub fn building(&self) -> Option<Building> {
+        Building::from_code((self.flags & BUILDING_MASK) >> BUILDING_SHIFT)
+    }
+    pub fn set_building(&mut self, building: Option<Building>) {
+        self.flags = (self.flags & !BUILDING_MASK) | (building.map_or(0, Building::code) << BUILDING_SHIFT);
+    }
+    pub fn biome(&self) -> Biome {
+        Biome::from_height(self.height())
+    }
+    /// discrete elevation, 0 is sea level and water
+    pub fn level(&self) -> i32 {
+        (self.height() / LEVEL_HEIGHT).ceil().max(0.0) as i32
+    }
+}
+
+/// Terrain type of a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Water,
+    Sand,
+    Grass,
+    Rock,
+}
+
+impl Biome {
+    /// classify terrain by height bands
+    pub fn from_height(height: f32) -> Self {
+        if height <= 0.0 {
+            Biome::Water
+        }
+        else if height <= 1.0 {
+            Biome::Sand
+        }
+        else if height <= 2.0 {
+            Biome::Grass
+        }
+        else {
+            Biome::Rock
+        }
+    }
+    /// `height` moved into the height band of this biome
+    pub fn clamp_height(self, height: f32) -> f32 {
+        // bands are open below
+        const MARGIN: f32 = 0.01;
+        match self {
+            Biome::Water => height.min(0.0),
+            Biome::Sand => height.clamp(MARGIN, 1.0),
+            Biome::Grass => height.clamp(1.0 + MARGIN, 2.0),
+            Biome::Rock => height.max(2.0 + MARGIN),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(unused)]
+pub enum Resource {
+    Tree,
+}
+
+impl Resource {
+    /// Non-zero code stored in the tile flags
+    fn code(self) -> u8 {
+        match self {
+            Resource::Tree => 1,
+        }
+    }
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Resource::Tree),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(unused)]
+pub enum Building {
+    House,
+    Forester,
+}
+
+impl Building {
+    /// Non-zero code stored in the tile flags
+    fn code(self) -> u8 {
+        match self {
+            Building::House => 1,
+            Building::Forester => 2,
+        }
+    }
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Building::House),
+            2 => Some(Building::Forester),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,132 @@
+//! Saving and loading of worlds
+//!
+//! A saved world is a YAML file with the generated chunks and the islands. Tile heights are stored
+//! row by row, resources and buildings only where there are any.
+use super::island::tile::{Building, Resource, Tile};
+use super::island::heightmap::{Pipeline, PipelineError};
+use super::island::{Island, IslandParams};
+use super::{Chunk, ChunkIndex, World};
+use crate::glob::types::*;
+use crate::grid::Grid;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SaveError {
+    #[error("Failed to write {0}: {1}")]
+    Write(String, std::io::Error),
+    #[error("Failed to read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Failed to serialize world: {0}")]
+    Serialize(serde_yaml::Error),
+    #[error("Failed to parse {0}: {1}")]
+    Parse(String, serde_yaml::Error),
+    #[error("Island {0} has {1} heights for {2} x {3} tiles")]
+    Size(usize, usize, usize, usize),
+    #[error("Saved recipe is invalid: {0}")]
+    Recipe(PipelineError),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedWorld {
+    chunks: Vec<(isize, isize)>,
+    islands: Vec<SavedIsland>,
+    #[serde(default)]
+    recipe: Option<Pipeline>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedIsland {
+    /// world position of the upper left tile
+    origin: (f32, f32),
+    width: usize,
+    height: usize,
+    /// tile heights, `height` rows of `width` tiles
+    heights: Vec<f32>,
+    /// tile index and resource of every tile with a resource
+    resources: Vec<(usize, usize, Resource)>,
+    buildings: Vec<(usize, usize, Building)>,
+    /// generation parameters, random for worlds saved before they were recorded
+    #[serde(default)]
+    params: Option<IslandParams>,
+}
+
+impl World {
+    /// Write the world to `path`
+    pub fn save(&self, path: &str) -> Result<(), SaveError> {
+        let saved = SavedWorld {
+            chunks: self.chunks.keys().map(|ind| (ind.x, ind.y)).collect(),
+            islands: self.islands.iter().map(SavedIsland::new).collect(),
+            recipe: self.recipe.clone(),
+        };
+        let content = serde_yaml::to_string(&saved).map_err(SaveError::Serialize)?;
+        std::fs::write(path, content).map_err(|e| SaveError::Write(path.to_string(), e))?;
+        log::info!("Saved world to {}", path);
+        Ok(())
+    }
+
+    /// Read a world written by `save` from `path`
+    pub fn load(path: &str) -> Result<Self, SaveError> {
+        let content = std::fs::read_to_string(path).map_err(|e| SaveError::Read(path.to_string(), e))?;
+        let saved: SavedWorld = serde_yaml::from_str(&content).map_err(|e| SaveError::Parse(path.to_string(), e))?;
+        if let Some(recipe) = &saved.recipe {
+            recipe.validate().map_err(SaveError::Recipe)?;
+        }
+        let mut world = World {
+            recipe: saved.recipe,
+            ..World::default()
+        };
+        for (x, y) in saved.chunks {
+            world.chunks.insert(ChunkIndex::new(x, y), Chunk::new());
+        }
+        for (i, island) in saved.islands.into_iter().enumerate() {
+            world.islands.push(island.into_island(i)?);
+        }
+        world.update_clipping_rect();
+        log::info!("Loaded world from {}", path);
+        Ok(world)
+    }
+}
+
+impl SavedIsland {
+    fn new(island: &Island) -> Self {
+        let mut saved = SavedIsland {
+            origin: (island.clipping_rect.origin.x, island.clipping_rect.origin.y),
+            width: island.tiles.width(),
+            height: island.tiles.height(),
+            heights: island.tiles.iter().map(Tile::height).collect(),
+            resources: Vec::new(),
+            buildings: Vec::new(),
+            params: Some(island.params),
+        };
+        for (index, tile) in island.tiles.indexed_iter() {
+            saved.resources.extend(tile.resource().map(|resource| (index.x, index.y, resource)));
+            saved.buildings.extend(tile.building().map(|building| (index.x, index.y, building)));
+        }
+        saved
+    }
+
+    /// Island number `index` of the saved world
+    fn into_island(self, index: usize) -> Result<Island, SaveError> {
+        let heights_len = self.heights.len();
+        let heights = Grid::from_vec(self.width, self.height, self.heights)
+            .ok_or(SaveError::Size(index, heights_len, self.width, self.height))?;
+        let origin = WorldCoordinate::new(self.origin.0, self.origin.1);
+        let mut tiles = heights.map(|height| Tile::new(*height));
+        for (x, y, resource) in self.resources {
+            if let Some(tile) = tiles.get_mut(x, y) {
+                tile.set_resource(Some(resource));
+            }
+        }
+        for (x, y, building) in self.buildings {
+            if let Some(tile) = tiles.get_mut(x, y) {
+                tile.set_building(Some(building));
+            }
+        }
+        Ok(Island {
+            clipping_rect: WorldRect::new(origin, WorldVector::new(self.width as f32, self.height as f32).to_size()),
+            tiles,
+            params: self.params.unwrap_or_else(IslandParams::random),
+        })
+    }
+}